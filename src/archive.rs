@@ -0,0 +1,384 @@
+use download::{Download, Downloader};
+use feedly::Feedly;
+use filetype::{self, FileType};
+use generated::EntryDetail;
+use hash::ContentHashes;
+use hyper::Url;
+use ledger::{self, Ledger};
+use perceptual::{Fingerprint, NearDuplicates, Verdict};
+use result::{FdownError, Result};
+use rewrite::Rewriter;
+use sink::Sink;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use template::{PathTemplate, TemplateValues};
+
+/// An image that was downloaded and stored.
+#[derive(Debug)]
+pub struct StoredImage {
+  /// The candidate URL that worked.
+  pub url: String,
+  /// Where the image was stored or, for a duplicate, where the same contents already were.
+  pub path: String,
+  /// Which candidate worked. Zero is the preferred, rewritten URL.
+  pub fallback: usize,
+  pub sha256: String,
+  /// True if the contents, or a copy at least as large, were already stored, so nothing new
+  /// was written.
+  pub duplicate: bool,
+  /// Only computed when near-duplicate detection is on.
+  pub fingerprint: Option<Fingerprint>,
+  /// A smaller copy of the same picture that this image replaced.
+  pub replaced: Option<String>,
+}
+
+/// What happened to the images in one entry.
+#[derive(Debug, Default)]
+pub struct EntryResult {
+  pub stored: Vec<StoredImage>,
+  /// Images that the ledger shows were stored by an earlier run.
+  pub skipped: usize,
+  /// The first error hit. Later images are still attempted.
+  pub error: Option<FdownError>,
+}
+
+impl EntryResult {
+  fn failed(err: FdownError) -> EntryResult {
+    EntryResult { error: Some(err), ..Default::default() }
+  }
+}
+
+/// Downloads the images in entries and stores them in a sink. Shared by the download workers.
+pub struct Archiver {
+  downloader: Downloader,
+  rewriter: Rewriter,
+  sink: Box<Sink>,
+  template: PathTemplate,
+  categories: HashMap<String, String>,
+  ledger: Mutex<Ledger>,
+  // sha256 -> path for the images stored or being stored by this run.
+  claimed: Mutex<HashMap<String, String>>,
+  // The paths the sink has written to in this run.
+  written: Mutex<HashSet<String>>,
+  near_duplicates: Option<Mutex<NearDuplicates>>,
+}
+
+impl Archiver {
+  pub fn new(downloader: Downloader,
+             rewriter: Rewriter,
+             sink: Box<Sink>,
+             template: PathTemplate,
+             categories: HashMap<String, String>,
+             ledger: Ledger)
+      -> Archiver {
+    Archiver {
+      downloader: downloader,
+      rewriter: rewriter,
+      sink: sink,
+      template: template,
+      categories: categories,
+      ledger: Mutex::new(ledger),
+      claimed: Mutex::new(HashMap::new()),
+      written: Mutex::new(HashSet::new()),
+      near_duplicates: None,
+    }
+  }
+
+  /// Turns on perceptual duplicate detection: images whose hashes differ in at most
+  /// `threshold` bits are the same picture, and only the largest copy is kept.
+  pub fn set_near_duplicate_distance(&mut self, threshold: u32) {
+    let mut near = NearDuplicates::new(threshold);
+    {
+      let ledger = self.ledger.lock().unwrap();
+      for record in ledger.records() {
+        let fields = (record.dhash, record.pixels, record.duplicate);
+        if let (Some(dhash), Some(pixels), None) = fields {
+          if ledger.is_live(&record.path) {
+            near.add(Fingerprint { dhash: dhash, pixels: pixels }, &record.path);
+          }
+        }
+      }
+    }
+    self.near_duplicates = Some(Mutex::new(near));
+  }
+
+  /// True if the ledger shows every image in `entry` was stored, leaving nothing to retry.
+  pub fn is_stored(&self, entry: &EntryDetail) -> bool {
+    let ledger = self.ledger.lock().unwrap();
+    ledger.contains_entry(entry) &&
+    Feedly::extract_image_urls(entry)
+      .iter()
+      .all(|url| self.rewriter.candidates(url).iter().any(|c| ledger.contains_url(c)))
+  }
+
+  pub fn archive_entry(&self, entry: &EntryDetail) -> EntryResult {
+    let images: Vec<Vec<String>> = Feedly::extract_image_urls(entry)
+      .iter()
+      .map(|url| self.rewriter.candidates(url))
+      .collect();
+    if images.is_empty() {
+      return EntryResult::failed(FdownError::MissingUrl(entry.id.clone()));
+    }
+
+    // Feedly's idea of the content type only applies to the visual.
+    let visual = entry.visual.as_ref();
+    let visual_url = visual.and_then(|v| v.url.as_ref());
+    let visual_type = visual.and_then(|v| v.content_type.as_ref()).map(|t| t.as_str());
+
+    let mut result = EntryResult::default();
+    let numbered = images.len() > 1;
+    for (i, candidates) in images.iter().enumerate() {
+      if candidates.iter().any(|url| self.ledger.lock().unwrap().contains_url(url)) {
+        result.skipped += 1;
+        continue;
+      }
+      let number = if numbered { Some(i + 1) } else { None };
+      // The unmodified URL is always the last candidate.
+      let feedly_type = if visual_url == candidates.last() { visual_type } else { None };
+      match self.archive_image(entry, candidates, number, feedly_type) {
+        Ok(image) => result.stored.push(image),
+        Err(e) => {
+          if result.error.is_none() {
+            result.error = Some(e);
+          }
+        }
+      }
+    }
+    result
+  }
+
+  /// Adds the images stored for `entry` to the ledger.
+  pub fn record(&self, entry: &EntryDetail, result: &EntryResult) -> Result<()> {
+    let mut ledger = self.ledger.lock().unwrap();
+    for image in &result.stored {
+      let mut record = ledger::new_record(entry, &image.url, &image.path);
+      record.sha256 = Some(image.sha256.clone());
+      record.duplicate = if image.duplicate { Some(true) } else { None };
+      record.dhash = image.fingerprint.map(|f| f.dhash);
+      record.pixels = image.fingerprint.map(|f| f.pixels);
+      record.replaces = image.replaced.clone();
+      try!(ledger.record(record));
+    }
+    Ok(())
+  }
+
+  /// Tries each candidate URL in turn until one of them is an image.
+  fn archive_image(&self,
+                   entry: &EntryDetail,
+                   candidates: &[String],
+                   number: Option<usize>,
+                   feedly_type: Option<&str>)
+      -> Result<StoredImage> {
+    let mut last_err = None;
+    for (i, url) in candidates.iter().enumerate() {
+      match self.download_image(url, feedly_type) {
+        Ok((download, file_type)) => {
+          return self.store_image(entry, url, i, &download, file_type, number);
+        }
+        Err(ref e) if is_missing(e) && i + 1 < candidates.len() => {}
+        Err(e) => last_err = Some(e),
+      }
+    }
+    Err(last_err.unwrap_or(FdownError::MissingUrl(entry.id.clone())))
+  }
+
+  fn download_image(&self,
+                    url: &str,
+                    feedly_type: Option<&str>)
+      -> Result<(Download, Option<FileType>)> {
+    let download = try!(self.downloader.download(url));
+    let file_type = filetype::detect(download.head(),
+                                     download.content_type.as_ref().map(|t| t.as_str()),
+                                     feedly_type);
+    if file_type == Some(FileType::Html) {
+      return Err(FdownError::NotAnImage(url.to_string()));
+    }
+    Ok((download, file_type))
+  }
+
+  fn store_image(&self,
+                 entry: &EntryDetail,
+                 url: &str,
+                 fallback: usize,
+                 download: &Download,
+                 file_type: Option<FileType>,
+                 number: Option<usize>)
+      -> Result<StoredImage> {
+    let mut filename = try!(filename_for_url(url));
+    if let Some(file_type) = file_type {
+      filename = filetype::fix_extension(&filename, file_type);
+    }
+    let filename = match number {
+      Some(n) => numbered_filename(&filename, n),
+      None => filename,
+    };
+
+    let origin = entry.origin.as_ref();
+    let values = TemplateValues {
+      category: origin.and_then(|o| self.categories.get(&o.stream_id)).map(|c| c.as_str()),
+      feed_title: origin.and_then(|o| o.title.as_ref()).map(|t| t.as_str()),
+      timestamp: entry.published.or(entry.crawled),
+      filename: &filename,
+    };
+    let dest = self.template.expand(&values);
+    let hashes = try!(ContentHashes::from_reader(try!(download.reader())));
+    let mut image = StoredImage {
+      url: url.to_string(),
+      path: dest,
+      fallback: fallback,
+      sha256: hashes.sha256.clone(),
+      duplicate: false,
+      fingerprint: None,
+      replaced: None,
+    };
+    if let Some(existing) = try!(self.claim(&hashes, &image.path)) {
+      image.path = existing;
+      image.duplicate = true;
+      return Ok(image);
+    }
+
+    if let Some(ref near) = self.near_duplicates {
+      // Too big to decode in memory, which is mostly video anyway.
+      image.fingerprint = download.bytes().and_then(Fingerprint::of);
+      if let Some(fingerprint) = image.fingerprint {
+        match near.lock().unwrap().check(fingerprint, &image.path) {
+          Verdict::New => {}
+          Verdict::DuplicateOf(existing) => {
+            self.claimed.lock().unwrap().remove(&hashes.sha256);
+            image.path = existing;
+            image.duplicate = true;
+            return Ok(image);
+          }
+          Verdict::Supersedes(smaller) => image.replaced = Some(smaller),
+        }
+      }
+    }
+
+    let stored = match self.sink.store(image.path.as_str(), download) {
+      Ok(stored) => stored,
+      Err(e) => {
+        self.claimed.lock().unwrap().remove(&hashes.sha256);
+        if let Some(ref near) = self.near_duplicates {
+          near.lock().unwrap().remove(&image.path);
+        }
+        return Err(e);
+      }
+    };
+    // The sink picks another name if the requested one is taken.
+    if stored != image.path {
+      self.claimed.lock().unwrap().insert(hashes.sha256.clone(), stored.clone());
+      if let (Some(near), Some(fingerprint)) = (self.near_duplicates.as_ref(), image.fingerprint) {
+        near.lock().unwrap().rename(fingerprint, &image.path, &stored);
+      }
+      image.path = stored;
+    }
+    self.written.lock().unwrap().insert(image.path.clone());
+    if let Some(smaller) = image.replaced.take() {
+      // Only remove what the sink wrote. Anything else at that path belongs to another image.
+      if self.wrote(&smaller) {
+        try!(self.sink.remove(&smaller));
+        self.written.lock().unwrap().remove(&smaller);
+        self.claimed.lock().unwrap().retain(|_, path| path != &smaller);
+        image.replaced = Some(smaller);
+      }
+    }
+    Ok(image)
+  }
+
+  /// True if the sink wrote the image at `path`, in this run or an earlier one, and it has
+  /// not been replaced since.
+  fn wrote(&self, path: &str) -> bool {
+    self.written.lock().unwrap().contains(path) || self.ledger.lock().unwrap().is_live(path)
+  }
+
+  /// Claims `hashes` for `dest`, unless the same contents were stored by an earlier run, are
+  /// being stored by another worker, or are already in the sink. Returns the existing path
+  /// in those cases.
+  fn claim(&self, hashes: &ContentHashes, dest: &str) -> Result<Option<String>> {
+    if let Some(path) = self.ledger.lock().unwrap().path_for_sha256(&hashes.sha256) {
+      return Ok(Some(path.clone()));
+    }
+    {
+      let mut claimed = self.claimed.lock().unwrap();
+      if let Some(path) = claimed.get(&hashes.sha256) {
+        return Ok(Some(path.clone()));
+      }
+      claimed.insert(hashes.sha256.clone(), dest.to_string());
+    }
+    match self.sink.find_duplicate(hashes) {
+      Ok(None) => Ok(None),
+      result => {
+        self.claimed.lock().unwrap().remove(&hashes.sha256);
+        result
+      }
+    }
+  }
+}
+
+/// True for errors that mean this URL has no image, so the next candidate is worth a try.
+fn is_missing(err: &FdownError) -> bool {
+  match *err {
+    FdownError::HttpStatus { status, .. } => status == 403 || status == 404 || status == 410,
+    FdownError::NotAnImage(_) => true,
+    _ => false,
+  }
+}
+
+fn filename_for_url(url: &str) -> Result<String> {
+  let parsed = try!(Url::parse(url)
+    .map_err(|_| FdownError::BadFormat(format!("unable to parse url: {}", url))));
+  let path = parsed.path();
+  let filename = &path[path.rfind('/').map_or(0, |slash| slash + 1)..];
+  if filename.is_empty() {
+    return Err(FdownError::BadFormat(format!("unable to extract filename from url: {}", url)));
+  }
+  Ok(filename.to_string())
+}
+
+/// Turns `name.jpg` into `name_2.jpg`, for entries with more than one image.
+fn numbered_filename(filename: &str, number: usize) -> String {
+  match filename.rfind('.') {
+    Some(dot) if dot > 0 => format!("{}_{}{}", &filename[..dot], number, &filename[dot..]),
+    _ => format!("{}_{}", filename, number),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use result::FdownError;
+  use super::{filename_for_url, is_missing, numbered_filename};
+
+  fn status_error(status: u16) -> FdownError {
+    FdownError::HttpStatus {
+      status: status,
+      url: "http://example.com/".to_string(),
+      message: None,
+      retry_after: None,
+    }
+  }
+
+  #[test]
+  fn missing() {
+    assert!(is_missing(&status_error(404)));
+    assert!(is_missing(&status_error(410)));
+    assert!(is_missing(&FdownError::NotAnImage("http://example.com/".to_string())));
+    assert!(!is_missing(&status_error(500)));
+    assert!(!is_missing(&FdownError::MissingUrl("id".to_string())));
+  }
+
+  #[test]
+  fn filename() {
+    assert_eq!("foo.jpg", filename_for_url("http://a.com/x/foo.jpg").unwrap());
+    assert_eq!("foo.jpg", filename_for_url("http://a.com/foo.jpg?w=500#frag").unwrap());
+    filename_for_url("http://a.com/x/").unwrap_err();
+    filename_for_url("not a url").unwrap_err();
+  }
+
+  #[test]
+  fn numbered() {
+    assert_eq!("foo_1.jpg", numbered_filename("foo.jpg", 1));
+    assert_eq!("foo.tar_12.gz", numbered_filename("foo.tar.gz", 12));
+    assert_eq!("foo_2", numbered_filename("foo", 2));
+    assert_eq!(".hidden_3", numbered_filename(".hidden", 3));
+  }
+}
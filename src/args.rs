@@ -1,14 +1,54 @@
-use clap::{App, Arg, ArgMatches};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use result::{FdownError, Result};
 use std::env;
 use std::ffi::OsString;
+use std::result;
 
 const CONFIG: &'static str = "config";
 const COUNT: &'static str = "count";
 const CATEGORY: &'static str = "category";
 const DEFAULT_CONFIG: &'static str = "~/.fdown";
-const SUBS: &'static str = "subs";
+const DUMP_ENTRIES: &'static str = "dump-entries";
+const DUMP_FORMAT: &'static str = "dump-format";
+const DUMP_RAW: &'static str = "dump-raw";
+const DUMP_SUBS: &'static str = "dump-subs";
+const ENTRY_ID: &'static str = "entry-id";
+const EXCLUDE_CATEGORY: &'static str = "exclude-category";
+const EXCLUDE_FEED: &'static str = "exclude-feed";
+const FEED: &'static str = "feed";
+const FEED_ID: &'static str = "feed-id";
+const HISTORY: &'static str = "history";
+const JOBS: &'static str = "jobs";
+const LEDGER: &'static str = "ledger";
+const MARK_READ: &'static str = "mark-read";
+const OUTPUT_DIR: &'static str = "output-dir";
+const PATH_TEMPLATE: &'static str = "path-template";
+const STREAM: &'static str = "stream";
+const TAG: &'static str = "tag";
+const UNREAD_ONLY: &'static str = "unread-only";
 const UNSAVE: &'static str = "unsave";
+const UNTAG: &'static str = "untag";
+
+// Subcommands.
+const CATEGORIES_CMD: &'static str = "categories";
+const ENTRIES_CMD: &'static str = "entries";
+const FETCH_CMD: &'static str = "fetch";
+const LOGIN_CMD: &'static str = "login";
+const STATUS_CMD: &'static str = "status";
+const SUBS_CMD: &'static str = "subs";
+const UNSAVE_CMD: &'static str = "unsave";
+
+/// What fdown has been asked to do.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+  Categories,
+  Entries,
+  Fetch,
+  Login,
+  Status,
+  Subs,
+  Unsave,
+}
 
 pub struct Args<'a> {
   matches: ArgMatches<'a>,
@@ -26,30 +66,240 @@ impl<'a> Args<'a> {
     Ok(Args { matches: matches })
   }
 
+  pub fn command(&self) -> Command {
+    match self.matches.subcommand_name() {
+      Some(CATEGORIES_CMD) => Command::Categories,
+      Some(ENTRIES_CMD) => Command::Entries,
+      Some(FETCH_CMD) => Command::Fetch,
+      Some(LOGIN_CMD) => Command::Login,
+      Some(STATUS_CMD) => Command::Status,
+      Some(SUBS_CMD) => Command::Subs,
+      Some(UNSAVE_CMD) => Command::Unsave,
+      _ => unreachable!("clap requires a subcommand"),
+    }
+  }
+
+  /// The options given to the subcommand.
+  fn sub(&self) -> Option<&ArgMatches<'a>> {
+    self.matches.subcommand().1
+  }
+
+  fn value_of(&self, name: &str) -> Option<&str> {
+    self.sub().and_then(|m| m.value_of(name))
+  }
+
+  fn values_of(&self, name: &str) -> Vec<&str> {
+    self.sub().and_then(|m| m.values_of(name)).map_or(vec![], |values| values.collect())
+  }
+
+  fn is_present(&self, name: &str) -> bool {
+    self.sub().map_or(false, |m| m.is_present(name))
+  }
+
   pub fn config_file_location(&self) -> &str {
     // Return something else and error if file not found
     return self.matches.value_of(CONFIG).unwrap_or(DEFAULT_CONFIG);
   }
 
-  pub fn list_subs(&self) -> bool {
-    self.matches.occurrences_of(SUBS) > 0
+  pub fn list_history(&self) -> bool {
+    self.is_present(HISTORY)
+  }
+
+  pub fn jobs(&self) -> usize {
+    // Checked by is_positive.
+    self.value_of(JOBS).unwrap_or("4").parse::<usize>().unwrap()
+  }
+
+  pub fn ledger_file(&self) -> Option<&str> {
+    self.value_of(LEDGER)
   }
 
   pub fn should_unsave(&self) -> bool {
-    self.matches.occurrences_of(UNSAVE) > 0
+    self.is_present(UNSAVE)
+  }
+
+  pub fn should_mark_read(&self) -> bool {
+    self.is_present(MARK_READ)
+  }
+
+  /// Labels of the tags to add to the entries after storing their images.
+  pub fn tags(&self) -> Vec<&str> {
+    self.values_of(TAG)
+  }
+
+  /// Labels of the tags to remove from the entries after storing their images.
+  pub fn untags(&self) -> Vec<&str> {
+    self.values_of(UNTAG)
   }
 
   pub fn filter_category(&self) -> Option<&str> {
-    self.matches.value_of(CATEGORY)
+    self.value_of(CATEGORY)
+  }
+
+  /// The Feedly stream to read entries from, as understood by `Feedly::stream`.
+  pub fn stream(&self) -> &str {
+    self.value_of(STREAM).unwrap_or("saved")
+  }
+
+  pub fn unread_only(&self) -> bool {
+    self.is_present(UNREAD_ONLY)
+  }
+
+  /// Title patterns of the feeds to process.
+  pub fn feeds(&self) -> Vec<&str> {
+    self.values_of(FEED)
+  }
+
+  /// Stream ids of the feeds to process.
+  pub fn feed_ids(&self) -> Vec<&str> {
+    self.values_of(FEED_ID)
+  }
+
+  pub fn excluded_feeds(&self) -> Vec<&str> {
+    self.values_of(EXCLUDE_FEED)
+  }
+
+  pub fn excluded_categories(&self) -> Vec<&str> {
+    self.values_of(EXCLUDE_CATEGORY)
   }
 
   pub fn number_of_entries(&self) -> usize {
-    self.matches.value_of(COUNT).unwrap_or("20").parse::<usize>().unwrap()
+    // Checked by is_positive.
+    self.value_of(COUNT).unwrap_or("20").parse::<usize>().unwrap()
   }
 
-  pub fn write_to_directory(&self) -> bool {
-    false
+  /// The entries named on the command line, for `unsave`.
+  pub fn entry_ids(&self) -> Vec<&str> {
+    self.values_of(ENTRY_ID)
   }
+
+  pub fn output_dir(&self) -> Option<&str> {
+    self.value_of(OUTPUT_DIR)
+  }
+
+  /// Where to write the entries as JSON.
+  pub fn dump_entries(&self) -> Option<&str> {
+    self.value_of(DUMP_ENTRIES)
+  }
+
+  /// Where to write the subscriptions as JSON.
+  pub fn dump_subs(&self) -> Option<&str> {
+    self.value_of(DUMP_SUBS)
+  }
+
+  /// "json" or "ndjson", if given. Otherwise the format follows the dump file's extension.
+  pub fn dump_format(&self) -> Option<&str> {
+    self.value_of(DUMP_FORMAT)
+  }
+
+  /// Whether to dump Feedly's responses as they were sent, rather than fdown's models.
+  pub fn dump_raw(&self) -> bool {
+    self.is_present(DUMP_RAW)
+  }
+
+  pub fn path_template(&self) -> Option<&str> {
+    self.value_of(PATH_TEMPLATE)
+  }
+}
+
+/// Validates numeric options, so that bad values are reported as usage errors.
+fn is_positive(value: String) -> result::Result<(), String> {
+  match value.parse::<usize>() {
+    Ok(n) if n > 0 => Ok(()),
+    _ => Err(format!("expected a number greater than zero, not \"{}\"", value)),
+  }
+}
+
+fn category_arg<'a, 'b>(help: &'b str) -> Arg<'a, 'b> {
+  Arg::with_name(CATEGORY)
+    .short("C")
+    .long(CATEGORY)
+    .takes_value(true)
+    .help(help)
+}
+
+fn count_arg<'a, 'b>(help: &'b str) -> Arg<'a, 'b> {
+  Arg::with_name(COUNT)
+    .long(COUNT)
+    .short("c")
+    .takes_value(true)
+    .validator(is_positive)
+    .help(help)
+}
+
+/// Options that pick feeds, on top of `--category`. Each may be given more than once.
+fn feed_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+  vec![Arg::with_name(FEED)
+         .long(FEED)
+         .takes_value(true)
+         .multiple(true)
+         .number_of_values(1)
+         .help("Only process feeds whose titles match this glob, or /regex/"),
+       Arg::with_name(FEED_ID)
+         .long(FEED_ID)
+         .takes_value(true)
+         .multiple(true)
+         .number_of_values(1)
+         .help("Only process the feed with this id, e.g. feed/http://example.com/rss"),
+       Arg::with_name(EXCLUDE_FEED)
+         .long(EXCLUDE_FEED)
+         .takes_value(true)
+         .multiple(true)
+         .number_of_values(1)
+         .help("Skip feeds whose titles match this glob, or /regex/"),
+       Arg::with_name(EXCLUDE_CATEGORY)
+         .long(EXCLUDE_CATEGORY)
+         .takes_value(true)
+         .multiple(true)
+         .number_of_values(1)
+         .help("Skip the feeds in this category")]
+}
+
+/// Options that pick the stream to read.
+fn stream_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+  vec![Arg::with_name(STREAM)
+         .short("s")
+         .long(STREAM)
+         .takes_value(true)
+         .help("Stream to read: saved (the default), all, category/<label>, tag/<label>, or a \
+                stream id such as feed/http://example.com/rss"),
+       Arg::with_name(UNREAD_ONLY)
+         .long(UNREAD_ONLY)
+         .help("Only read unread entries")]
+}
+
+fn ledger_arg<'a, 'b>() -> Arg<'a, 'b> {
+  Arg::with_name(LEDGER)
+    .long(LEDGER)
+    .takes_value(true)
+    .help("Location of the ledger of stored images")
+}
+
+fn output_dir_arg<'a, 'b>() -> Arg<'a, 'b> {
+  Arg::with_name(OUTPUT_DIR)
+    .short("o")
+    .long(OUTPUT_DIR)
+    .takes_value(true)
+    .help("Write images to this local directory instead of Dropbox")
+}
+
+/// Options that write what Feedly returned to `name`'s file, e.g. `--dump-entries`.
+fn dump_args<'a, 'b>(name: &'a str, help: &'b str) -> Vec<Arg<'a, 'b>> {
+  vec![Arg::with_name(name)
+         .long(name)
+         .takes_value(true)
+         .help(help),
+       Arg::with_name(DUMP_FORMAT)
+         .long(DUMP_FORMAT)
+         .takes_value(true)
+         .possible_values(&["json", "ndjson"])
+         .requires(name)
+         .help("Format of the dump: a pretty JSON array, or one record per line. Defaults to \
+                ndjson for .ndjson and .jsonl files, and json otherwise"),
+       Arg::with_name(DUMP_RAW)
+         .long(DUMP_RAW)
+         .requires(name)
+         .help("Dump Feedly's responses as they were sent, instead of the parsed fields")]
 }
 
 fn parse_cmd_line_from<'a, I, T>(itr: I) -> Result<ArgMatches<'a>>
@@ -58,28 +308,79 @@ fn parse_cmd_line_from<'a, I, T>(itr: I) -> Result<ArgMatches<'a>>
   let builder = App::new("fdown")
     .version("0.0.1")
     .author("George Madrid (gmadrid@gmail.com)")
-    .arg(Arg::with_name(CATEGORY)
-      .short("C")
-      .long(CATEGORY)
-      .help("Only process entries in this category")
-      .takes_value(true))
+    .setting(AppSettings::SubcommandRequiredElseHelp)
     .arg(Arg::with_name(CONFIG)
       .long(CONFIG)
       .takes_value(true)
       .help("Location of the config file"))
-    .arg(Arg::with_name(COUNT)
-      .long(COUNT)
-      .short("c")
-      .takes_value(true)
-      .help("Number of entries to download"))
-    .arg(Arg::with_name(SUBS)
-      .long(SUBS)
-      .help("List the subscriptions"))
-    .arg(Arg::with_name(UNSAVE)
-      .short("U")
-      .long(UNSAVE)
-      .help("Unsave the entry after saving it.")
-      .requires(CATEGORY));
+    .subcommand(SubCommand::with_name(FETCH_CMD)
+      .about("Download the images in saved entries, or another stream, and store them")
+      .args(&stream_args())
+      .arg(category_arg("Only process entries in this category"))
+      .args(&feed_args())
+      .arg(count_arg("Number of entries to download"))
+      .args(&dump_args(DUMP_ENTRIES, "Write the entries to be stored to this file as JSON"))
+      .arg(Arg::with_name(JOBS)
+        .short("j")
+        .long(JOBS)
+        .takes_value(true)
+        .validator(is_positive)
+        .help("Number of images to download and store at once"))
+      .arg(ledger_arg())
+      .arg(output_dir_arg())
+      .arg(Arg::with_name(PATH_TEMPLATE)
+        .long(PATH_TEMPLATE)
+        .takes_value(true)
+        .help("Destination path for each image, e.g. \
+               {category}/{feed_title}/{yyyy}/{mm}/{filename}"))
+      .arg(Arg::with_name(UNSAVE)
+        .short("U")
+        .long(UNSAVE)
+        .help("Unsave the entries after storing their images"))
+      .arg(Arg::with_name(MARK_READ)
+        .long(MARK_READ)
+        .help("Mark the entries as read after storing their images"))
+      .arg(Arg::with_name(TAG)
+        .long(TAG)
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .help("Tag the entries with this label after storing their images, e.g. fdown/archived"))
+      .arg(Arg::with_name(UNTAG)
+        .long(UNTAG)
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .help("Remove the tag with this label from the entries after storing their images")))
+    .subcommand(SubCommand::with_name(SUBS_CMD)
+      .about("List the subscriptions")
+      .args(&dump_args(DUMP_SUBS, "Write the subscriptions to this file as JSON")))
+    .subcommand(SubCommand::with_name(CATEGORIES_CMD).about("List the categories"))
+    .subcommand(SubCommand::with_name(ENTRIES_CMD)
+      .about("List the saved entries, or the entries in another stream")
+      .args(&stream_args())
+      .arg(category_arg("Only list entries in this category"))
+      .args(&feed_args())
+      .arg(count_arg("Number of entries to list"))
+      .args(&dump_args(DUMP_ENTRIES, "Write the entries to this file as JSON")))
+    .subcommand(SubCommand::with_name(UNSAVE_CMD)
+      .about("Unsave entries without storing anything")
+      .arg(category_arg("Unsave the saved entries in this category"))
+      .args(&feed_args())
+      .arg(count_arg("Number of entries to unsave from the category"))
+      .arg(Arg::with_name(ENTRY_ID)
+        .multiple(true)
+        .required_unless_one(&[CATEGORY, FEED, FEED_ID])
+        .help("Ids of the entries to unsave")))
+    .subcommand(SubCommand::with_name(STATUS_CMD)
+      .about("Show the configuration and what has been stored")
+      .arg(Arg::with_name(HISTORY)
+        .long(HISTORY)
+        .help("List the images already stored, from the ledger"))
+      .arg(ledger_arg())
+      .arg(output_dir_arg()))
+    .subcommand(SubCommand::with_name(LOGIN_CMD)
+      .about("Log in to Feedly in a browser and save the tokens in the config file"));
 
   builder.get_matches_from_safe(itr).map_err(FdownError::from)
 }
@@ -93,74 +394,310 @@ mod tests {
     Args::parse_from(lst.iter()).unwrap()
   }
 
+  #[test]
+  fn commands() {
+    assert_eq!(Command::Fetch, args_from(&["foo", "fetch"]).command());
+    assert_eq!(Command::Subs, args_from(&["foo", "subs"]).command());
+    assert_eq!(Command::Categories, args_from(&["foo", "categories"]).command());
+    assert_eq!(Command::Entries, args_from(&["foo", "entries"]).command());
+    assert_eq!(Command::Unsave, args_from(&["foo", "unsave", "id1"]).command());
+    assert_eq!(Command::Status, args_from(&["foo", "status"]).command());
+  }
+
+  #[test]
+  #[should_panic]
+  fn command_missing() {
+    args_from(&["foo"]);
+  }
+
+  #[test]
+  #[should_panic]
+  fn command_unknown() {
+    args_from(&["foo", "bar"]);
+  }
+
+  #[test]
+  fn login() {
+    assert_eq!(Command::Login, args_from(&["foo", "login"]).command());
+    let args = args_from(&["foo", "--config", "/tmp/fdown", "login"]);
+    assert_eq!(Command::Login, args.command());
+    assert_eq!("/tmp/fdown", args.config_file_location());
+  }
+
   #[test]
   fn count() {
     // Test default
-    let args = args_from(&["foo"]);
+    let args = args_from(&["foo", "fetch"]);
     assert_eq!(20, args.number_of_entries());
 
-    let args = args_from(&["foo", "--count", "57"]);
+    let args = args_from(&["foo", "fetch", "--count", "57"]);
     assert_eq!(57, args.number_of_entries());
+
+    let args = args_from(&["foo", "entries", "-c", "3"]);
+    assert_eq!(3, args.number_of_entries());
+  }
+
+  #[test]
+  fn count_invalid() {
+    assert!(Args::parse_from(["foo", "fetch", "--count", "many"].iter()).is_err());
+    assert!(Args::parse_from(["foo", "entries", "-c", "0"].iter()).is_err());
+    assert!(Args::parse_from(["foo", "unsave", "-C", "x", "-c", "-3"].iter()).is_err());
   }
 
   #[test]
   #[should_panic]
   fn count_missing() {
-    args_from(&["foo", "--count"]);
+    args_from(&["foo", "fetch", "--count"]);
+  }
+
+  #[test]
+  #[should_panic]
+  fn count_before_command() {
+    args_from(&["foo", "--count", "5", "fetch"]);
   }
 
   #[test]
   fn filter_category() {
-    let args = args_from(&["foo"]);
+    let args = args_from(&["foo", "fetch"]);
     assert_eq!(None, args.filter_category());
 
-    let args = args_from(&["foo", "-C", "quux"]);
+    let args = args_from(&["foo", "fetch", "-C", "quux"]);
     assert_eq!("quux", args.filter_category().unwrap());
 
-    let args = args_from(&["foo", "--category", "bam"]);
+    let args = args_from(&["foo", "entries", "--category", "bam"]);
     assert_eq!("bam", args.filter_category().unwrap());
   }
 
   #[test]
   #[should_panic]
   fn filter_category_missing() {
-    args_from(&["foo", "-C"]);
+    args_from(&["foo", "fetch", "-C"]);
+  }
+
+  #[test]
+  #[should_panic]
+  fn filter_category_subs() {
+    args_from(&["foo", "subs", "-C", "quux"]);
+  }
+
+  #[test]
+  fn feeds() {
+    let args = args_from(&["foo", "fetch"]);
+    assert!(args.feeds().is_empty());
+    assert!(args.feed_ids().is_empty());
+    assert!(args.excluded_feeds().is_empty());
+    assert!(args.excluded_categories().is_empty());
+
+    let args = args_from(&["foo",
+                           "entries",
+                           "--feed",
+                           "cats*",
+                           "--feed",
+                           "/dogs/",
+                           "--feed-id",
+                           "feed/http://example.com/rss",
+                           "--exclude-feed",
+                           "*weekly",
+                           "--exclude-category",
+                           "tech",
+                           "--exclude-category",
+                           "news"]);
+    assert_eq!(vec!["cats*", "/dogs/"], args.feeds());
+    assert_eq!(vec!["feed/http://example.com/rss"], args.feed_ids());
+    assert_eq!(vec!["*weekly"], args.excluded_feeds());
+    assert_eq!(vec!["tech", "news"], args.excluded_categories());
+  }
+
+  #[test]
+  fn stream() {
+    let args = args_from(&["foo", "fetch"]);
+    assert_eq!("saved", args.stream());
+    assert_eq!(false, args.unread_only());
+
+    let args = args_from(&["foo", "fetch", "--stream", "all", "--unread-only"]);
+    assert_eq!("all", args.stream());
+    assert_eq!(true, args.unread_only());
+
+    let args = args_from(&["foo", "entries", "-s", "feed/http://example.com/rss"]);
+    assert_eq!("feed/http://example.com/rss", args.stream());
+  }
+
+  #[test]
+  #[should_panic]
+  fn stream_unsave() {
+    args_from(&["foo", "unsave", "--stream", "all", "id1"]);
+  }
+
+  #[test]
+  fn history() {
+    let args = args_from(&["foo", "status", "--history"]);
+    assert_eq!(true, args.list_history());
+
+    let args = args_from(&["foo", "status"]);
+    assert_eq!(false, args.list_history());
+  }
+
+  #[test]
+  fn jobs() {
+    let args = args_from(&["foo", "fetch"]);
+    assert_eq!(4, args.jobs());
+
+    let args = args_from(&["foo", "fetch", "-j", "8"]);
+    assert_eq!(8, args.jobs());
+
+    let args = args_from(&["foo", "fetch", "--jobs", "1"]);
+    assert_eq!(1, args.jobs());
+  }
+
+  #[test]
+  fn jobs_invalid() {
+    assert!(Args::parse_from(["foo", "fetch", "--jobs", "x"].iter()).is_err());
+    assert!(Args::parse_from(["foo", "fetch", "-j", "0"].iter()).is_err());
+    assert!(Args::parse_from(["foo", "fetch", "-j", "-3"].iter()).is_err());
+  }
+
+  #[test]
+  fn ledger_file() {
+    let args = args_from(&["foo", "fetch"]);
+    assert_eq!(None, args.ledger_file());
+
+    let args = args_from(&["foo", "fetch", "--ledger", "/tmp/ledger"]);
+    assert_eq!("/tmp/ledger", args.ledger_file().unwrap());
+
+    let args = args_from(&["foo", "status", "--ledger", "/tmp/ledger"]);
+    assert_eq!("/tmp/ledger", args.ledger_file().unwrap());
+  }
+
+  #[test]
+  fn output_dir() {
+    let args = args_from(&["foo", "fetch"]);
+    assert_eq!(None, args.output_dir());
+
+    let args = args_from(&["foo", "fetch", "-o", "/tmp/images"]);
+    assert_eq!("/tmp/images", args.output_dir().unwrap());
+
+    let args = args_from(&["foo", "fetch", "--output-dir", "images"]);
+    assert_eq!("images", args.output_dir().unwrap());
+  }
+
+  #[test]
+  #[should_panic]
+  fn output_dir_missing() {
+    args_from(&["foo", "fetch", "--output-dir"]);
   }
 
   #[test]
-  fn subs() {
-    let args = args_from(&["foo", "--subs"]);
-    assert_eq!(true, args.list_subs());
+  fn dump() {
+    let args = args_from(&["foo", "fetch"]);
+    assert_eq!(None, args.dump_entries());
+    assert_eq!(None, args.dump_format());
+    assert_eq!(false, args.dump_raw());
+
+    let args = args_from(&["foo", "entries", "--dump-entries", "/tmp/entries.json"]);
+    assert_eq!("/tmp/entries.json", args.dump_entries().unwrap());
+    assert_eq!(None, args.dump_subs());
+
+    let args = args_from(&["foo",
+                           "subs",
+                           "--dump-subs",
+                           "subs.out",
+                           "--dump-format",
+                           "ndjson",
+                           "--dump-raw"]);
+    assert_eq!("subs.out", args.dump_subs().unwrap());
+    assert_eq!("ndjson", args.dump_format().unwrap());
+    assert_eq!(true, args.dump_raw());
+  }
 
-    let args = args_from(&["foo"]);
-    assert_eq!(false, args.list_subs());
+  #[test]
+  #[should_panic]
+  fn dump_format_unknown() {
+    args_from(&["foo", "entries", "--dump-entries", "e.json", "--dump-format", "xml"]);
+  }
+
+  #[test]
+  #[should_panic]
+  fn dump_raw_without_path() {
+    args_from(&["foo", "fetch", "--dump-raw"]);
+  }
+
+  #[test]
+  #[should_panic]
+  fn dump_subs_fetch() {
+    args_from(&["foo", "fetch", "--dump-subs", "subs.json"]);
+  }
+
+  #[test]
+  fn path_template() {
+    let args = args_from(&["foo", "fetch"]);
+    assert_eq!(None, args.path_template());
+
+    let args = args_from(&["foo", "fetch", "--path-template", "{yyyy}/{filename}"]);
+    assert_eq!("{yyyy}/{filename}", args.path_template().unwrap());
   }
 
   #[test]
   fn unsave() {
-    let args = args_from(&["foo", "-C", "cat"]);
+    let args = args_from(&["foo", "fetch"]);
     assert_eq!(false, args.should_unsave());
 
-    let args = args_from(&["foo", "-C", "cat", "-U"]);
+    // No longer needs a category.
+    let args = args_from(&["foo", "fetch", "-U"]);
     assert_eq!(true, args.should_unsave());
 
-    let args = args_from(&["foo", "-C", "cat", "--unsave"]);
+    let args = args_from(&["foo", "fetch", "-C", "cat", "--unsave"]);
     assert_eq!(true, args.should_unsave());
   }
 
+  #[test]
+  fn actions() {
+    let args = args_from(&["foo", "fetch"]);
+    assert_eq!(false, args.should_mark_read());
+    assert!(args.tags().is_empty());
+    assert!(args.untags().is_empty());
+
+    let args = args_from(&["foo",
+                           "fetch",
+                           "--mark-read",
+                           "--tag",
+                           "fdown/archived",
+                           "--tag",
+                           "keep",
+                           "--untag",
+                           "todo"]);
+    assert_eq!(true, args.should_mark_read());
+    assert_eq!(vec!["fdown/archived", "keep"], args.tags());
+    assert_eq!(vec!["todo"], args.untags());
+  }
+
+  #[test]
+  fn unsave_command() {
+    let args = args_from(&["foo", "unsave", "id1", "id2"]);
+    assert_eq!(vec!["id1", "id2"], args.entry_ids());
+    assert_eq!(None, args.filter_category());
+
+    let args = args_from(&["foo", "unsave", "-C", "cat", "-c", "5"]);
+    assert!(args.entry_ids().is_empty());
+    assert_eq!("cat", args.filter_category().unwrap());
+    assert_eq!(5, args.number_of_entries());
+
+    let args = args_from(&["foo", "unsave", "--feed", "cats*"]);
+    assert_eq!(vec!["cats*"], args.feeds());
+  }
+
   #[test]
   #[should_panic]
-  fn unsave_no_cat() {
-    args_from(&["foo", "-U"]);
+  fn unsave_command_nothing() {
+    args_from(&["foo", "unsave"]);
   }
 
   #[test]
   fn config_file_location() {
-    let args = Args::parse_from(["foo", "--config", "foobar"].iter()).unwrap();
+    let args = Args::parse_from(["foo", "--config", "foobar", "subs"].iter()).unwrap();
     assert_eq!("foobar", args.config_file_location());
 
     // Test default
-    let args = Args::parse_from(["foo"].iter()).unwrap();
+    let args = Args::parse_from(["foo", "subs"].iter()).unwrap();
     assert_eq!("~/.fdown", args.config_file_location());
   }
 
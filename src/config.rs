@@ -1,9 +1,17 @@
 use result::{FdownError, Result};
 use std::collections::hash_map::HashMap;
 use std::env::home_dir;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
 use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+lazy_static! {
+  // Feedly and Dropbox tokens may both be refreshed, from different threads.
+  static ref UPDATE_LOCK: Mutex<()> = Mutex::new(());
+}
 
 #[derive(Debug)]
 pub struct ConfigFile {
@@ -12,7 +20,7 @@ pub struct ConfigFile {
 
 impl ConfigFile {
   pub fn new(filename: &str) -> Result<ConfigFile> {
-    let f = try!(File::open(twiddle(filename, BaseHomedirProvider {}).unwrap_or(PathBuf::from(filename))));
+    let f = try!(File::open(expand_path(filename)));
     let reader = BufReader::new(f);
 
     ConfigFile::new_with_bufread(reader)
@@ -40,6 +48,110 @@ impl ConfigFile {
       .get(k)
       .ok_or(FdownError::BadConfig(format!("Required config value, {}, missing", k)))
   }
+
+  pub fn optional_string(&self, k: &str) -> Option<&String> {
+    self.values.get(k)
+  }
+
+  /// All the values whose keys start with `prefix`, ordered by key.
+  pub fn values_with_prefix(&self, prefix: &str) -> Vec<&String> {
+    let mut keys: Vec<&String> = self.values.keys().filter(|k| k.starts_with(prefix)).collect();
+    keys.sort();
+    keys.into_iter().map(|k| &self.values[k]).collect()
+  }
+
+  pub fn optional_u64(&self, k: &str) -> Result<Option<u64>> {
+    match self.values.get(k) {
+      Some(v) => Ok(Some(try!(v.parse::<u64>()))),
+      None => Ok(None),
+    }
+  }
+}
+
+/// Sets `updates` in the config file, keeping its other lines and comments. Creates the file
+/// if it does not exist.
+pub fn update_file(filename: &str, updates: &[(&str, &str)]) -> Result<()> {
+  let _lock = UPDATE_LOCK.lock().unwrap();
+  let path = expand_path(filename);
+  let mut contents = String::new();
+  match File::open(&path) {
+    Ok(mut f) => {
+      try!(f.read_to_string(&mut contents));
+    }
+    Err(ref e) if e.kind() == ErrorKind::NotFound => {}
+    Err(e) => return Err(FdownError::from(e)),
+  }
+  replace_file(&path, update_lines(&contents, updates).as_bytes())
+}
+
+/// Writes `contents` to a file next to `path` and renames it over `path`, so that a failed
+/// write leaves the original, and the tokens in it, as they were. The original's permissions
+/// are kept, and a symlinked config is updated where it points.
+fn replace_file(path: &Path, contents: &[u8]) -> Result<()> {
+  let target = match fs::canonicalize(path) {
+    Ok(target) => target,
+    Err(ref e) if e.kind() == ErrorKind::NotFound => path.to_path_buf(),
+    Err(e) => return Err(FdownError::from(e)),
+  };
+  let permissions = fs::metadata(&target).ok().map(|metadata| metadata.permissions());
+  let mut temp_name = target.file_name().map_or(Default::default(), |name| name.to_os_string());
+  temp_name.push(".tmp");
+  let temp = target.with_file_name(temp_name);
+  let _ = fs::remove_file(&temp);
+  let written = create_private(&temp)
+    .and_then(|mut f| {
+      if let Some(permissions) = permissions {
+        try!(fs::set_permissions(&temp, permissions));
+      }
+      try!(f.write_all(contents));
+      f.sync_all()
+    })
+    .and_then(|_| fs::rename(&temp, &target));
+  if let Err(e) = written {
+    let _ = fs::remove_file(&temp);
+    return Err(FdownError::from(e));
+  }
+  Ok(())
+}
+
+/// Creates a file only the user can read, since configs hold tokens.
+#[cfg(unix)]
+fn create_private(path: &Path) -> io::Result<File> {
+  OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)
+}
+
+#[cfg(not(unix))]
+fn create_private(path: &Path) -> io::Result<File> {
+  OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+fn update_lines(contents: &str, updates: &[(&str, &str)]) -> String {
+  let mut done = vec![false; updates.len()];
+  let mut result = String::new();
+  for line in contents.lines() {
+    let key = line.find('=').map(|pos| line[..pos].trim());
+    match updates.iter().position(|&(k, _)| Some(k) == key) {
+      Some(i) if !line.trim().starts_with("#") => {
+        result.push_str(&format!("{}={}\n", updates[i].0, updates[i].1));
+        done[i] = true;
+      }
+      _ => {
+        result.push_str(line);
+        result.push('\n');
+      }
+    }
+  }
+  for (i, &(k, v)) in updates.iter().enumerate() {
+    if !done[i] {
+      result.push_str(&format!("{}={}\n", k, v));
+    }
+  }
+  result
+}
+
+/// Expands a leading `~` in `filename` to the user's home directory.
+pub fn expand_path(filename: &str) -> PathBuf {
+  twiddle(filename, BaseHomedirProvider {}).unwrap_or(PathBuf::from(filename))
 }
 
 fn twiddle<T>(filename: &str, home_dir_provider: T) -> Option<PathBuf>
@@ -82,8 +194,31 @@ impl HasHomedir for BaseHomedirProvider {
 
 #[cfg(test)]
 mod tests {
-  use std::path::PathBuf;
-  use super::{HasHomedir, split_line_at_first_equals};
+  use std::env;
+  use std::fs::{self, File};
+  use std::io::{Read, Write};
+  #[cfg(unix)]
+  use std::os::unix::fs::{PermissionsExt, symlink};
+  use std::path::{Path, PathBuf};
+
+  #[cfg(unix)]
+  fn set_mode(path: &Path, mode: u32) {
+    fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+  }
+
+  #[cfg(unix)]
+  fn mode_of(path: &Path) -> Option<u32> {
+    Some(fs::metadata(path).unwrap().permissions().mode() & 0o777)
+  }
+
+  #[cfg(not(unix))]
+  fn set_mode(_path: &Path, _mode: u32) {}
+
+  #[cfg(not(unix))]
+  fn mode_of(_path: &Path) -> Option<u32> {
+    None
+  }
+  use super::{HasHomedir, split_line_at_first_equals, update_file, update_lines};
 
   #[test]
   fn simple_reader() {
@@ -93,6 +228,31 @@ mod tests {
     assert_eq!("bam", config.required_string("quux").unwrap());
   }
 
+  #[test]
+  fn optional_string() {
+    let reader = "foo=bar".as_bytes();
+    let config = super::ConfigFile::new_with_bufread(reader).unwrap();
+    assert_eq!("bar", config.optional_string("foo").unwrap());
+    assert_eq!(None, config.optional_string("quux"));
+  }
+
+  #[test]
+  fn optional_u64() {
+    let reader = "foo=17\nbar=baz".as_bytes();
+    let config = super::ConfigFile::new_with_bufread(reader).unwrap();
+    assert_eq!(Some(17), config.optional_u64("foo").unwrap());
+    assert_eq!(None, config.optional_u64("quux").unwrap());
+    config.optional_u64("bar").unwrap_err();
+  }
+
+  #[test]
+  fn values_with_prefix() {
+    let reader = "rule.b=2\nother=x\nrule.a=1\nrulez=3".as_bytes();
+    let config = super::ConfigFile::new_with_bufread(reader).unwrap();
+    assert_eq!(vec!["1", "2"], config.values_with_prefix("rule."));
+    assert_eq!(0, config.values_with_prefix("missing.").len());
+  }
+
   #[test]
   fn comments_blank_lines() {
     let reader = "\nquux=bam\n# a comment\nfoo=bar\n".as_bytes();
@@ -153,4 +313,72 @@ mod tests {
   fn twiddle_no_home_dir_with_absolute_path() {
     super::twiddle("/quux", FailingHomeDirProvider {});
   }
+
+  #[test]
+  fn update() {
+    let contents = "# token = commented\nuserid=old\ntoken = older\ndropboxFolder=/x\n";
+    assert_eq!("# token = commented\nuserid=me\ntoken=new\ndropboxFolder=/x\nrefreshToken=r\n",
+               update_lines(contents,
+                            &[("userid", "me"), ("token", "new"), ("refreshToken", "r")]));
+    assert_eq!("token=new\n", update_lines("", &[("token", "new")]));
+  }
+
+  #[test]
+  fn contents_of(path: &Path) -> String {
+    let mut contents = String::new();
+    File::open(path).unwrap().read_to_string(&mut contents).unwrap();
+    contents
+  }
+
+  #[test]
+  fn update_in_place() {
+    let dir = env::temp_dir().join("fdown_config_update");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("fdown");
+    File::create(&path).unwrap().write_all(b"userid=me\ntoken=old\n").unwrap();
+    set_mode(&path, 0o640);
+
+    update_file(path.to_str().unwrap(), &[("token", "new")]).unwrap();
+    assert_eq!("userid=me\ntoken=new\n", contents_of(&path));
+    // Only the config file is left.
+    assert_eq!(1, fs::read_dir(&dir).unwrap().count());
+    if let Some(mode) = mode_of(&path) {
+      assert_eq!(0o640, mode);
+    }
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn update_new_file_is_private() {
+    let dir = env::temp_dir().join("fdown_config_new");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("fdown");
+    update_file(path.to_str().unwrap(), &[("token", "new")]).unwrap();
+    assert_eq!(Some(0o600), mode_of(&path));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn update_through_symlink() {
+    let dir = env::temp_dir().join("fdown_config_symlink");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("dotfiles")).unwrap();
+    let target = dir.join("dotfiles").join("fdown");
+    File::create(&target).unwrap().write_all(b"token=old\n").unwrap();
+    let link = dir.join("fdown");
+    symlink(&target, &link).unwrap();
+
+    update_file(link.to_str().unwrap(), &[("token", "new")]).unwrap();
+    assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+    assert_eq!("token=new\n", contents_of(&target));
+  }
+
+  #[test]
+  fn update_missing_directory() {
+    let path = env::temp_dir().join("fdown_config_missing").join("fdown");
+    let _ = fs::remove_dir_all(path.parent().unwrap());
+    update_file(path.to_str().unwrap(), &[("token", "new")]).unwrap_err();
+  }
 }
@@ -0,0 +1,149 @@
+use http::{HttpResponse, check_status};
+use hyper::Client;
+use result::Result;
+use retry::RetryPolicy;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+use tempfile;
+
+/// Bodies bigger than this are spooled to a temporary file rather than held in memory.
+pub const SPOOL_THRESHOLD: u64 = 16 * 1024 * 1024;
+/// How much of a spooled body is kept in memory, for sniffing its file type.
+const HEAD_LEN: usize = 512;
+
+enum Body {
+  Memory(Vec<u8>),
+  Spooled { file: File, len: u64, head: Vec<u8> },
+}
+
+/// The body of a successful download.
+pub struct Download {
+  body: Body,
+  /// The response's Content-Type header, if it had one.
+  pub content_type: Option<String>,
+}
+
+impl Download {
+  pub fn in_memory(bytes: Vec<u8>, content_type: Option<String>) -> Download {
+    Download {
+      body: Body::Memory(bytes),
+      content_type: content_type,
+    }
+  }
+
+  /// Reads `reader` to the end, spooling it to a temporary file if it is bigger than
+  /// `threshold`.
+  fn read_from<R>(mut reader: R, threshold: u64, content_type: Option<String>) -> Result<Download>
+    where R: Read {
+    let mut bytes = Vec::new();
+    try!(reader.by_ref().take(threshold).read_to_end(&mut bytes));
+    if (bytes.len() as u64) < threshold {
+      return Ok(Download::in_memory(bytes, content_type));
+    }
+    let mut file = try!(tempfile::tempfile());
+    try!(file.write_all(&bytes));
+    let len = bytes.len() as u64 + try!(io::copy(&mut reader, &mut file));
+    bytes.truncate(HEAD_LEN);
+    Ok(Download {
+      body: Body::Spooled {
+        file: file,
+        len: len,
+        head: bytes,
+      },
+      content_type: content_type,
+    })
+  }
+
+  pub fn len(&self) -> u64 {
+    match self.body {
+      Body::Memory(ref bytes) => bytes.len() as u64,
+      Body::Spooled { len, .. } => len,
+    }
+  }
+
+  /// The start of the body, enough to sniff its file type.
+  pub fn head(&self) -> &[u8] {
+    match self.body {
+      Body::Memory(ref bytes) => bytes,
+      Body::Spooled { ref head, .. } => head,
+    }
+  }
+
+  /// The whole body, unless it was too big to keep in memory.
+  pub fn bytes(&self) -> Option<&[u8]> {
+    match self.body {
+      Body::Memory(ref bytes) => Some(bytes),
+      Body::Spooled { .. } => None,
+    }
+  }
+
+  /// Reads the whole body from the start. Can be called more than once.
+  pub fn reader<'a>(&'a self) -> Result<Box<Read + 'a>> {
+    match self.body {
+      Body::Memory(ref bytes) => Ok(Box::new(&bytes[..])),
+      Body::Spooled { ref file, .. } => {
+        let mut file = try!(file.try_clone());
+        try!(file.seek(SeekFrom::Start(0)));
+        Ok(Box::new(file))
+      }
+    }
+  }
+}
+
+/// Fetches images, retrying transient failures.
+pub struct Downloader {
+  client: Arc<Client>,
+  retry: RetryPolicy,
+}
+
+impl Downloader {
+  pub fn new(client: Arc<Client>, retry: RetryPolicy) -> Downloader {
+    Downloader {
+      client: client,
+      retry: retry,
+    }
+  }
+
+  pub fn download(&self, url: &str) -> Result<Download> {
+    self.retry.run(|| {
+      let response = try!(check_status(url, try!(self.client.get(url).send())));
+      let content_type = response.header("Content-Type");
+      Download::read_from(response, SPOOL_THRESHOLD, content_type)
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Read;
+  use super::*;
+
+  fn read_all(download: &Download) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    download.reader().unwrap().read_to_end(&mut bytes).unwrap();
+    bytes
+  }
+
+  #[test]
+  fn small_bodies_stay_in_memory() {
+    let download = Download::read_from(&b"hello"[..], 10, None).unwrap();
+    assert_eq!(5, download.len());
+    assert_eq!(Some(&b"hello"[..]), download.bytes());
+    assert_eq!(b"hello", download.head());
+    assert_eq!(b"hello".to_vec(), read_all(&download));
+  }
+
+  #[test]
+  fn big_bodies_are_spooled() {
+    let body = vec![7u8; 2000];
+    let download = Download::read_from(&body[..], 1000, Some("image/gif".to_string())).unwrap();
+    assert_eq!(2000, download.len());
+    assert_eq!(None, download.bytes());
+    assert_eq!(HEAD_LEN, download.head().len());
+    assert_eq!(Some("image/gif".to_string()), download.content_type);
+    // Readable more than once.
+    assert_eq!(body, read_all(&download));
+    assert_eq!(body, read_all(&download));
+  }
+}
@@ -1,47 +1,656 @@
-use generated::DropboxUploadAPI;
+use config;
+use download::Download;
+use generated::{DropboxDeleteArg, DropboxFileMetadata, DropboxListFolderArg,
+                DropboxListFolderContinueArg, DropboxListFolderResult, DropboxUploadAPI,
+                DropboxUploadSessionAppendArg, DropboxUploadSessionCursor,
+                DropboxUploadSessionFinishArg, DropboxUploadSessionStartArg,
+                DropboxUploadSessionStartResult, OAuthTokenResponse};
+use http::{HttpMockableClient, HyperClientWrapper, RefreshToken, check_status, form_body};
 use hyper::Client;
-use hyper::header::{Authorization, ContentType};
+use hyper::header::{Authorization, ContentType, Headers};
 use hyper::mime::{Mime, SubLevel, TopLevel};
-use result::Result;
+use result::{FdownError, Result};
+use retry::RetryPolicy;
+use serde::{Deserialize, Serialize};
 use serde_json;
+use std::collections::HashMap;
+use std::io::{self, ErrorKind, Read};
+use std::sync::{Arc, Mutex};
 
 header!{ (DropboxAPIArg, "Dropbox-API-Arg") => [String] }
 
-const UPLOAD_URL: &'static str = "https://content.dropboxapi.com/2/files/upload";
+/// Where file contents are uploaded.
+pub const DEFAULT_CONTENT_URL: &'static str = "https://content.dropboxapi.com";
+/// Where everything else goes.
+pub const DEFAULT_API_URL: &'static str = "https://api.dropboxapi.com";
+
+// On the content server.
+const UPLOAD_PATH: &'static str = "/2/files/upload";
+const UPLOAD_SESSION_START_PATH: &'static str = "/2/files/upload_session/start";
+const UPLOAD_SESSION_APPEND_PATH: &'static str = "/2/files/upload_session/append_v2";
+const UPLOAD_SESSION_FINISH_PATH: &'static str = "/2/files/upload_session/finish";
+// On the API server.
+const TOKEN_PATH: &'static str = "/oauth2/token";
+const DELETE_PATH: &'static str = "/2/files/delete_v2";
+const LIST_FOLDER_PATH: &'static str = "/2/files/list_folder";
+const LIST_FOLDER_CONTINUE_PATH: &'static str = "/2/files/list_folder/continue";
 const ADD_UPLOAD_MODE: &'static str = "add";
+/// The upload endpoint rejects anything bigger. Larger files go through an upload session.
+const MAX_SINGLE_UPLOAD: u64 = 150 * 1024 * 1024;
+/// How much of a file each upload session request carries. A multiple of Dropbox's 4 MB
+/// block size.
+const SESSION_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+pub type Dropbox = DropboxInternal<HyperClientWrapper>;
 
-#[derive(Debug)]
-pub struct Dropbox {
-  token: String,
+pub struct DropboxInternal<T>
+  where T: HttpMockableClient
+{
+  // The current access token. None until the first one is fetched with the refresh token.
+  token: Mutex<Option<String>>,
+  refresh: Option<RefreshToken>,
+  // Where refreshed access tokens are saved, if anywhere.
+  config_file: Option<String>,
+  content_url: String,
+  api_url: String,
+  client: T,
+  retry: RetryPolicy,
 }
 
-impl Dropbox {
-  pub fn new(token: &str) -> Dropbox {
-    Dropbox { token: token.to_string() }
+impl<T> DropboxInternal<T>
+  where T: HttpMockableClient
+{
+  pub fn new(token: Option<&str>, client: Arc<Client>) -> DropboxInternal<HyperClientWrapper> {
+    DropboxInternal::<HyperClientWrapper>::new_with_client(token, HyperClientWrapper::new(client))
+  }
+
+  fn new_with_client<C>(token: Option<&str>, client: C) -> DropboxInternal<C>
+    where C: HttpMockableClient {
+    DropboxInternal {
+      token: Mutex::new(token.map(|t| t.to_string())),
+      refresh: None,
+      config_file: None,
+      content_url: DEFAULT_CONTENT_URL.to_string(),
+      api_url: DEFAULT_API_URL.to_string(),
+      client: client,
+      retry: RetryPolicy::default(),
+    }
+  }
+
+  /// Points the client at other servers, such as a local stub.
+  pub fn set_base_urls(&mut self, content_url: &str, api_url: &str) {
+    self.content_url = content_url.trim_right_matches('/').to_string();
+    self.api_url = api_url.trim_right_matches('/').to_string();
+  }
+
+  fn content_url(&self, path: &str) -> String {
+    format!("{}{}", self.content_url, path)
+  }
+
+  fn api_url(&self, path: &str) -> String {
+    format!("{}{}", self.api_url, path)
+  }
+
+  pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+    self.retry = retry;
+  }
+
+  /// Lets expired access tokens be replaced without asking the user for a new one.
+  pub fn set_refresh_token(&mut self, refresh: RefreshToken) {
+    self.refresh = Some(refresh);
+  }
+
+  /// Saves refreshed access tokens as `dropboxToken` in `config_file`, so that later runs
+  /// start with them.
+  pub fn save_tokens_to(&mut self, config_file: &str) {
+    self.config_file = Some(config_file.to_string());
   }
 
   fn auth_header(&self) -> Authorization<String> {
-    Authorization(format!("Bearer {}", self.token).to_owned())
+    let token = self.token.lock().unwrap();
+    Authorization(format!("Bearer {}", token.as_ref().map_or("", |t| t.as_str())))
+  }
+
+  /// Headers for an authorized request whose body is `content_type`.
+  fn headers(&self, content_type: ContentType) -> Headers {
+    let mut headers = Headers::new();
+    headers.set(content_type);
+    headers.set(self.auth_header());
+    headers
+  }
+
+  /// Runs `request`, and if the access token had expired, runs it again with a new one.
+  fn authorized<V, F>(&self, request: F) -> Result<V>
+    where F: Fn() -> Result<V> {
+    let token = try!(self.access_token());
+    match request() {
+      Err(ref e) if self.refresh.is_some() && is_expired_token(e) => {}
+      result => return result,
+    }
+    try!(self.refresh_access_token(&token));
+    request()
   }
 
-  fn api_header(&self, api: &DropboxUploadAPI) -> DropboxAPIArg {
-    DropboxAPIArg(serde_json::to_string(&api).unwrap())
+  /// Sends the request that `request` makes, retrying transient failures and replacing an
+  /// expired access token.
+  fn send<F>(&self, url: &str, request: F) -> Result<T::R>
+    where F: Fn() -> Result<T::R> {
+    self.authorized(|| self.retry.run(|| check_status(url, try!(request()))))
   }
 
-  pub fn upload(&self, path: &str, contents: &[u8]) -> Result<()> {
+  fn access_token(&self) -> Result<String> {
+    if let Some(ref token) = *self.token.lock().unwrap() {
+      return Ok(token.clone());
+    }
+    self.refresh_access_token("")
+  }
+
+  /// Replaces the `stale` access token. Workers that all see it expire at once wait here,
+  /// and only the first asks Dropbox for a new one.
+  fn refresh_access_token(&self, stale: &str) -> Result<String> {
+    let mut token = self.token.lock().unwrap();
+    if let Some(ref current) = *token {
+      if current != stale {
+        return Ok(current.clone());
+      }
+    }
+    let refresh = match self.refresh {
+      Some(ref refresh) => refresh,
+      None => {
+        return Err(FdownError::BadConfig("Dropbox access token expired, and no \
+                                          dropboxRefreshToken is configured"
+          .to_string()))
+      }
+    };
+    let body = form_body(&[("grant_type", "refresh_token"),
+                           ("refresh_token", refresh.refresh_token.as_str()),
+                           ("client_id", refresh.client_id.as_str()),
+                           ("client_secret", refresh.client_secret.as_str())]);
+    let url = self.api_url(TOKEN_PATH);
+    let response = try!(self.retry.run(|| {
+      let mut headers = Headers::new();
+      headers.set(ContentType::form_url_encoded());
+      let response = try!(self.client
+        .post_with_headers(&url, headers, &mut body.as_bytes(), body.len() as u64));
+      check_status(&url, response)
+    }));
+    let fresh: OAuthTokenResponse = try!(serde_json::from_reader(response));
+    if let Some(ref config_file) = self.config_file {
+      try!(config::update_file(config_file, &[("dropboxToken", &fresh.access_token)]));
+    }
+    *token = Some(fresh.access_token.clone());
+    Ok(fresh.access_token)
+  }
+
+  fn api_header<A>(&self, api: &A) -> DropboxAPIArg
+    where A: Serialize {
+    DropboxAPIArg(escape_non_ascii(&serde_json::to_string(api).unwrap()))
+  }
+
+  /// Uploads `contents` to `path`, streaming it through an upload session if it is too big
+  /// for a single request. Returns the path Dropbox stored it at, which has a number added
+  /// if `path` was taken.
+  pub fn upload(&self, path: &str, contents: &Download) -> Result<String> {
     let api = DropboxUploadAPI {
       path: path,
       mode: ADD_UPLOAD_MODE,
       autorename: true,
       mute: false,
     };
-    try!(Client::new()
-      .post(UPLOAD_URL)
-      .body(contents)
-      .header(ContentType(Mime(TopLevel::Application, SubLevel::OctetStream, vec![])))
-      .header(self.auth_header())
-      .header(self.api_header(&api))
-      .send());
+    if contents.len() > MAX_SINGLE_UPLOAD {
+      return self.upload_session(api, &mut *try!(contents.reader()), contents.len());
+    }
+    let url = self.content_url(UPLOAD_PATH);
+    let response = try!(self.send(&url, || {
+      let mut headers = self.headers(octet_stream());
+      headers.set(self.api_header(&api));
+      let mut reader = try!(contents.reader());
+      self.client.post_with_headers(&url, headers, &mut *reader, contents.len())
+    }));
+    let stored: DropboxFileMetadata = try!(serde_json::from_reader(response));
+    Ok(stored.path_display)
+  }
+
+  /// Sends the `len` bytes in `reader` a chunk at a time: the first with start, the last
+  /// with finish, and the rest with append_v2. Only one chunk is in memory at once.
+  fn upload_session(&self,
+                    commit: DropboxUploadAPI,
+                    mut reader: &mut Read,
+                    len: u64)
+      -> Result<String> {
+    let mut chunk = Vec::with_capacity(SESSION_CHUNK_SIZE as usize);
+    try!(read_chunk(&mut reader, &mut chunk));
+    let start = DropboxUploadSessionStartArg { close: false };
+    let response = try!(self.post_content(&self.content_url(UPLOAD_SESSION_START_PATH),
+                                          &start,
+                                          &chunk));
+    let started: DropboxUploadSessionStartResult = try!(serde_json::from_reader(response));
+    let mut offset = chunk.len() as u64;
+    loop {
+      try!(read_chunk(&mut reader, &mut chunk));
+      if chunk.is_empty() && offset < len {
+        return Err(FdownError::from(io::Error::new(ErrorKind::UnexpectedEof,
+                                                   format!("upload ended after {} of {} bytes",
+                                                           offset,
+                                                           len))));
+      }
+      let cursor = DropboxUploadSessionCursor {
+        session_id: &started.session_id,
+        offset: offset,
+      };
+      if offset + chunk.len() as u64 >= len {
+        let finish = DropboxUploadSessionFinishArg {
+          cursor: cursor,
+          commit: commit,
+        };
+        let response = try!(self.post_content(&self.content_url(UPLOAD_SESSION_FINISH_PATH),
+                                              &finish,
+                                              &chunk));
+        let stored: DropboxFileMetadata = try!(serde_json::from_reader(response));
+        return Ok(stored.path_display);
+      }
+      let append = DropboxUploadSessionAppendArg {
+        cursor: cursor,
+        close: false,
+      };
+      try!(self.post_content(&self.content_url(UPLOAD_SESSION_APPEND_PATH), &append, &chunk));
+      offset += chunk.len() as u64;
+    }
+  }
+
+  fn post_content<A>(&self, url: &str, arg: &A, body: &[u8]) -> Result<T::R>
+    where A: Serialize {
+    self.send(url, || {
+      let mut headers = self.headers(octet_stream());
+      headers.set(self.api_header(arg));
+      self.client.post_with_headers(url, headers, &mut &body[..], body.len() as u64)
+    })
+  }
+
+  pub fn delete(&self, path: &str) -> Result<()> {
+    let body = try!(serde_json::to_vec(&DropboxDeleteArg { path: path }));
+    let _: serde_json::Value = try!(self.post_json(&self.api_url(DELETE_PATH), &body));
     Ok(())
   }
+
+  /// Maps the `content_hash` of every file under `folder` to the file's path. A folder that
+  /// does not exist yet has no files.
+  pub fn content_hashes(&self, folder: &str) -> Result<HashMap<String, String>> {
+    let mut hashes = HashMap::new();
+    let arg = DropboxListFolderArg {
+      path: folder,
+      recursive: true,
+    };
+    let mut page: DropboxListFolderResult =
+      match self.post_json(&self.api_url(LIST_FOLDER_PATH), &try!(serde_json::to_vec(&arg))) {
+        Ok(page) => page,
+        Err(FdownError::HttpStatus { status: 409, ref message, .. })
+          if message.as_ref().map_or(false, |m| m.starts_with("path/not_found")) => {
+          return Ok(hashes);
+        }
+        Err(e) => return Err(e),
+      };
+    loop {
+      for entry in page.entries {
+        if entry.tag != "file" {
+          continue;
+        }
+        if let (Some(hash), Some(path)) = (entry.content_hash, entry.path_display) {
+          hashes.insert(hash, path);
+        }
+      }
+      if !page.has_more {
+        break;
+      }
+      let body = try!(serde_json::to_vec(&DropboxListFolderContinueArg { cursor: &page.cursor }));
+      page = try!(self.post_json(&self.api_url(LIST_FOLDER_CONTINUE_PATH), &body));
+    }
+    Ok(hashes)
+  }
+
+  fn post_json<V>(&self, url: &str, body: &[u8]) -> Result<V>
+    where V: Deserialize {
+    let response = try!(self.send(url, || {
+      self.client.post_with_headers(url,
+                                    self.headers(ContentType::json()),
+                                    &mut &body[..],
+                                    body.len() as u64)
+    }));
+    Ok(try!(serde_json::from_reader(response)))
+  }
+}
+
+fn octet_stream() -> ContentType {
+  ContentType(Mime(TopLevel::Application, SubLevel::OctetStream, vec![]))
+}
+
+/// Escapes everything from 0x7F up in `json` as `\uXXXX`, which Dropbox requires of JSON in
+/// headers. Characters outside the BMP become surrogate pairs.
+fn escape_non_ascii(json: &str) -> String {
+  let mut escaped = String::with_capacity(json.len());
+  for unit in json.encode_utf16() {
+    if unit < 0x7f {
+      escaped.push(unit as u8 as char);
+    } else {
+      escaped.push_str(&format!("\\u{:04x}", unit));
+    }
+  }
+  escaped
+}
+
+/// True for the 401 Dropbox returns once a short-lived access token has expired.
+fn is_expired_token(err: &FdownError) -> bool {
+  match *err {
+    FdownError::HttpStatus { status: 401, ref message, .. } => {
+      message.as_ref().map_or(false, |m| m.starts_with("expired_access_token"))
+    }
+    _ => false,
+  }
+}
+
+/// Replaces `chunk` with up to `SESSION_CHUNK_SIZE` more bytes from `reader`.
+fn read_chunk<R>(reader: &mut R, chunk: &mut Vec<u8>) -> Result<()>
+  where R: Read {
+  chunk.clear();
+  try!(reader.take(SESSION_CHUNK_SIZE).read_to_end(chunk));
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use download::Download;
+  use http::{HttpMockableClient, HttpResponse, RefreshToken};
+  use hyper::header::{self, Authorization, ContentType, Headers};
+  use result::{FdownError, Result};
+  use retry::RetryPolicy;
+  use std::cell::{Cell, RefCell};
+  use std::io::{self, Cursor, Read};
+  use super::*;
+
+  struct MockResponse {
+    status: u16,
+    body: Cursor<Vec<u8>>,
+  }
+  impl Read for MockResponse {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+      self.body.read(buf)
+    }
+  }
+  impl HttpResponse for MockResponse {
+    fn status(&self) -> u16 {
+      self.status
+    }
+    fn header(&self, _name: &str) -> Option<String> {
+      None
+    }
+  }
+
+  struct Request {
+    url: String,
+    headers: Headers,
+    body: Vec<u8>,
+  }
+
+  // Returns the responses in order and records every request.
+  struct MockClient {
+    responses: Vec<(u16, &'static str)>,
+    next_response: Cell<usize>,
+    requests: RefCell<Vec<Request>>,
+  }
+  impl HttpMockableClient for MockClient {
+    type R = MockResponse;
+
+    fn get(&self,
+           _url: &str,
+           _auth_header: Option<header::Authorization<String>>)
+        -> Result<Self::R> {
+      panic!("Dropbox only sends POSTs");
+    }
+    fn post(&self,
+            _url: &str,
+            _auth_header: Option<header::Authorization<String>>,
+            _body: &[u8])
+        -> Result<Self::R> {
+      panic!("Dropbox sends its own headers");
+    }
+    fn put(&self,
+           _url: &str,
+           _auth_header: Option<header::Authorization<String>>,
+           _body: &[u8])
+        -> Result<Self::R> {
+      panic!("Dropbox only sends POSTs");
+    }
+    fn delete(&self,
+              _url: &str,
+              _auth_header: Option<header::Authorization<String>>)
+        -> Result<Self::R> {
+      panic!("Dropbox only sends POSTs");
+    }
+    fn post_with_headers(&self,
+                         url: &str,
+                         headers: Headers,
+                         body: &mut Read,
+                         len: u64)
+        -> Result<Self::R> {
+      let mut bytes = Vec::new();
+      try!(body.read_to_end(&mut bytes));
+      assert_eq!(len, bytes.len() as u64);
+      self.requests.borrow_mut().push(Request {
+        url: url.to_string(),
+        headers: headers,
+        body: bytes,
+      });
+      let index = self.next_response.get();
+      if index >= self.responses.len() {
+        return Err(FdownError::TestError);
+      }
+      self.next_response.set(index + 1);
+      let (status, body) = self.responses[index];
+      Ok(MockResponse {
+        status: status,
+        body: Cursor::new(body.as_bytes().to_vec()),
+      })
+    }
+  }
+
+  type MockDropbox = DropboxInternal<MockClient>;
+
+  fn mock_dropbox(token: Option<&str>, responses: Vec<(u16, &'static str)>) -> MockDropbox {
+    let mut dropbox = Dropbox::new_with_client(token,
+                                               MockClient {
+                                                 responses: responses,
+                                                 next_response: Cell::new(0),
+                                                 requests: RefCell::new(Vec::new()),
+                                               });
+    dropbox.set_retry_policy(RetryPolicy::without_sleeping(3));
+    dropbox
+  }
+
+  fn test_refresh_token() -> RefreshToken {
+    RefreshToken {
+      refresh_token: "refresh".to_string(),
+      client_id: "key".to_string(),
+      client_secret: "secret".to_string(),
+    }
+  }
+
+  // What Dropbox returns for an upload to /Inbox/a.jpg.
+  const UPLOADED: &'static str = "{\"name\": \"a.jpg\", \"path_display\": \"/Inbox/a.jpg\"}";
+
+  fn upload_abc(dropbox: &MockDropbox) -> Result<String> {
+    dropbox.upload("/Inbox/a.jpg", &Download::in_memory(b"abc".to_vec(), None))
+  }
+
+  fn bearer(token: &str) -> Option<Authorization<String>> {
+    Some(Authorization(format!("Bearer {}", token)))
+  }
+
+  #[test]
+  fn upload() {
+    let dropbox = mock_dropbox(Some("tok"), vec![(200, UPLOADED)]);
+    assert_eq!("/Inbox/a.jpg", upload_abc(&dropbox).unwrap());
+    let requests = dropbox.client.requests.borrow();
+    assert_eq!(1, requests.len());
+    assert_eq!("https://content.dropboxapi.com/2/files/upload", requests[0].url);
+    assert_eq!(b"abc".to_vec(), requests[0].body);
+    assert_eq!(bearer("tok").as_ref(),
+               requests[0].headers.get::<Authorization<String>>());
+    assert_eq!(Some(&octet_stream()), requests[0].headers.get::<ContentType>());
+    let DropboxAPIArg(ref arg) = *requests[0].headers.get::<DropboxAPIArg>().unwrap();
+    assert_eq!("{\"path\":\"/Inbox/a.jpg\",\"mode\":\"add\",\"autorename\":true,\"mute\":false}",
+               arg);
+  }
+
+  #[test]
+  fn base_urls() {
+    let mut dropbox = mock_dropbox(Some("tok"), vec![(200, UPLOADED), (200, "{}")]);
+    dropbox.set_base_urls("http://localhost:9000/", "http://localhost:9001");
+    upload_abc(&dropbox).unwrap();
+    dropbox.delete("/Inbox/a.jpg").unwrap();
+    let requests = dropbox.client.requests.borrow();
+    assert_eq!("http://localhost:9000/2/files/upload", requests[0].url);
+    assert_eq!("http://localhost:9001/2/files/delete_v2", requests[1].url);
+    assert_eq!(Some(&ContentType::json()), requests[1].headers.get::<ContentType>());
+    assert_eq!("{\"path\":\"/Inbox/a.jpg\"}", String::from_utf8_lossy(&requests[1].body));
+  }
+
+  #[test]
+  fn upload_session_truncated() {
+    let dropbox = mock_dropbox(Some("tok"), vec![(200, "{\"session_id\": \"s1\"}")]);
+    let api = DropboxUploadAPI {
+      path: "/Inbox/big.mp4",
+      mode: "add",
+      autorename: true,
+      mute: false,
+    };
+    match dropbox.upload_session(api, &mut &b"abc"[..], 10) {
+      Err(FdownError::Io(e)) => assert_eq!(io::ErrorKind::UnexpectedEof, e.kind()),
+      _ => panic!("expected an Io error"),
+    }
+    let requests = dropbox.client.requests.borrow();
+    assert_eq!(1, requests.len());
+    assert_eq!("https://content.dropboxapi.com/2/files/upload_session/start", requests[0].url);
+    assert_eq!(b"abc".to_vec(), requests[0].body);
+  }
+
+  #[test]
+  fn upload_non_ascii_path() {
+    let dropbox = mock_dropbox(Some("tok"),
+                               vec![(200, "{\"path_display\": \"/Inbox/Café/a.jpg\"}")]);
+    let stored = dropbox.upload("/Inbox/Café/a.jpg", &Download::in_memory(b"abc".to_vec(), None))
+      .unwrap();
+    assert_eq!("/Inbox/Café/a.jpg", stored);
+    let requests = dropbox.client.requests.borrow();
+    let DropboxAPIArg(ref arg) = *requests[0].headers.get::<DropboxAPIArg>().unwrap();
+    assert_eq!("{\"path\":\"/Inbox/Caf\\u00e9/a.jpg\",\"mode\":\"add\",\"autorename\":true,\
+                \"mute\":false}",
+               arg);
+  }
+
+  #[test]
+  fn escapes_non_ascii() {
+    assert_eq!("{\"a\":\"plain\"}", escape_non_ascii("{\"a\":\"plain\"}"));
+    assert_eq!("\\u00e9\\u007f\\u65e5", escape_non_ascii("é\u{7f}日"));
+    assert_eq!("\\ud83d\\udcf7", escape_non_ascii("📷"));
+  }
+
+  #[test]
+  fn upload_renamed() {
+    let dropbox = mock_dropbox(Some("tok"),
+                               vec![(200, "{\"path_display\": \"/Inbox/a (1).jpg\"}")]);
+    assert_eq!("/Inbox/a (1).jpg", upload_abc(&dropbox).unwrap());
+  }
+
+  fn check_upload_error(body: &'static str, expected_message: &str) {
+    let dropbox = mock_dropbox(Some("tok"), vec![(409, body), (200, UPLOADED)]);
+    match upload_abc(&dropbox) {
+      Err(FdownError::HttpStatus { status, message, .. }) => {
+        assert_eq!(409, status);
+        assert_eq!(Some(expected_message.to_string()), message);
+      }
+      _ => panic!("expected an HttpStatus error"),
+    }
+    // Not worth retrying.
+    assert_eq!(1, dropbox.client.requests.borrow().len());
+  }
+
+  #[test]
+  fn conflict() {
+    check_upload_error("{\"error_summary\": \"path/conflict/file/..\", \
+                        \"error\": {\".tag\": \"path\"}}",
+                       "path/conflict/file/..");
+  }
+
+  #[test]
+  fn quota() {
+    check_upload_error("{\"error_summary\": \"path/insufficient_space/..\", \
+                        \"error\": {\".tag\": \"path\"}}",
+                       "path/insufficient_space/..");
+  }
+
+  #[test]
+  fn refreshes_expired_token() {
+    let mut dropbox = mock_dropbox(Some("old"),
+                                   vec![(401, "{\"error_summary\": \"expired_access_token/..\"}"),
+                                        (200, "{\"access_token\": \"new\", \"expires_in\": 14400}"),
+                                        (200, UPLOADED)]);
+    dropbox.set_refresh_token(test_refresh_token());
+    upload_abc(&dropbox).unwrap();
+    let requests = dropbox.client.requests.borrow();
+    assert_eq!(3, requests.len());
+    assert_eq!("https://api.dropboxapi.com/oauth2/token", requests[1].url);
+    assert_eq!(Some(&ContentType::form_url_encoded()),
+               requests[1].headers.get::<ContentType>());
+    assert_eq!(None, requests[1].headers.get::<Authorization<String>>());
+    assert_eq!("grant_type=refresh_token&refresh_token=refresh&client_id=key&client_secret=secret",
+               String::from_utf8_lossy(&requests[1].body));
+    assert_eq!(bearer("new").as_ref(),
+               requests[2].headers.get::<Authorization<String>>());
+  }
+
+  #[test]
+  fn fetches_first_token() {
+    let mut dropbox = mock_dropbox(None,
+                                   vec![(200, "{\"access_token\": \"new\"}"), (200, UPLOADED)]);
+    dropbox.set_refresh_token(test_refresh_token());
+    upload_abc(&dropbox).unwrap();
+    let requests = dropbox.client.requests.borrow();
+    assert_eq!(2, requests.len());
+    assert_eq!(bearer("new").as_ref(),
+               requests[1].headers.get::<Authorization<String>>());
+  }
+
+  #[test]
+  fn expired_token_without_refresh() {
+    let dropbox = mock_dropbox(Some("old"),
+                               vec![(401, "{\"error_summary\": \"expired_access_token/..\"}")]);
+    match upload_abc(&dropbox) {
+      Err(FdownError::HttpStatus { status, .. }) => assert_eq!(401, status),
+      _ => panic!("expected an HttpStatus error"),
+    }
+  }
+
+  #[test]
+  fn content_hashes() {
+    let first = "{\"entries\": [{\".tag\": \"file\", \"path_display\": \"/Inbox/a.jpg\", \
+                 \"content_hash\": \"aaa\"}, {\".tag\": \"folder\", \"path_display\": \
+                 \"/Inbox/sub\"}], \"cursor\": \"c1\", \"has_more\": true}";
+    let second = "{\"entries\": [{\".tag\": \"file\", \"path_display\": \"/Inbox/sub/b.jpg\", \
+                  \"content_hash\": \"bbb\"}], \"cursor\": \"c2\", \"has_more\": false}";
+    let dropbox = mock_dropbox(Some("tok"), vec![(200, first), (200, second)]);
+    let hashes = dropbox.content_hashes("/Inbox").unwrap();
+    assert_eq!(2, hashes.len());
+    assert_eq!("/Inbox/a.jpg", hashes["aaa"]);
+    assert_eq!("/Inbox/sub/b.jpg", hashes["bbb"]);
+    let requests = dropbox.client.requests.borrow();
+    assert_eq!("https://api.dropboxapi.com/2/files/list_folder/continue", requests[1].url);
+    assert_eq!("{\"cursor\":\"c1\"}", String::from_utf8_lossy(&requests[1].body));
+  }
+
+  #[test]
+  fn content_hashes_missing_folder() {
+    let dropbox = mock_dropbox(Some("tok"),
+                               vec![(409, "{\"error_summary\": \"path/not_found/..\"}")]);
+    assert!(dropbox.content_hashes("/Inbox").unwrap().is_empty());
+  }
 }
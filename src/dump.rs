@@ -0,0 +1,104 @@
+use config;
+use result::{FdownError, Result};
+use serde::Serialize;
+use serde_json;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// How records are laid out in a dump file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DumpFormat {
+  /// One pretty-printed array, like `sample_entries.json`.
+  Json,
+  /// One compact record per line.
+  Ndjson,
+}
+
+impl DumpFormat {
+  pub fn from_name(name: &str) -> Result<DumpFormat> {
+    match name {
+      "json" => Ok(DumpFormat::Json),
+      "ndjson" => Ok(DumpFormat::Ndjson),
+      _ => Err(FdownError::BadConfig(format!("Unknown dump format: {}", name))),
+    }
+  }
+
+  /// NDJSON for paths ending in `.ndjson` or `.jsonl`, and JSON otherwise.
+  pub fn for_path(path: &str) -> DumpFormat {
+    if path.ends_with(".ndjson") || path.ends_with(".jsonl") {
+      DumpFormat::Ndjson
+    } else {
+      DumpFormat::Json
+    }
+  }
+}
+
+/// Writes `records` to the file at `path`, replacing it.
+pub fn write<T>(path: &str, format: DumpFormat, records: &[T]) -> Result<()>
+  where T: Serialize {
+  let mut writer = BufWriter::new(try!(File::create(config::expand_path(path))));
+  try!(write_to(&mut writer, format, records));
+  try!(writer.flush());
+  Ok(())
+}
+
+fn write_to<W, T>(writer: &mut W, format: DumpFormat, records: &[T]) -> Result<()>
+  where W: Write,
+        T: Serialize {
+  match format {
+    DumpFormat::Json => {
+      try!(serde_json::to_writer_pretty(writer, &records));
+      try!(writer.write_all(b"\n"));
+    }
+    DumpFormat::Ndjson => {
+      for record in records {
+        try!(serde_json::to_writer(writer, record));
+        try!(writer.write_all(b"\n"));
+      }
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::Value;
+  use serde_json;
+  use super::*;
+
+  fn records() -> Vec<Value> {
+    serde_json::from_str("[{\"id\": \"a\", \"n\": 1}, {\"id\": \"b\", \"n\": 2}]").unwrap()
+  }
+
+  fn dumped(format: DumpFormat, records: &[Value]) -> String {
+    let mut out = Vec::new();
+    write_to(&mut out, format, records).unwrap();
+    String::from_utf8(out).unwrap()
+  }
+
+  #[test]
+  fn json() {
+    let out = dumped(DumpFormat::Json, &records());
+    assert!(out.starts_with("[\n  {\n"));
+    let parsed: Vec<Value> = serde_json::from_str(&out).unwrap();
+    assert_eq!(records(), parsed);
+    assert_eq!("[]\n", dumped(DumpFormat::Json, &[]));
+  }
+
+  #[test]
+  fn ndjson() {
+    assert_eq!("{\"id\":\"a\",\"n\":1}\n{\"id\":\"b\",\"n\":2}\n",
+               dumped(DumpFormat::Ndjson, &records()));
+    assert_eq!("", dumped(DumpFormat::Ndjson, &[]));
+  }
+
+  #[test]
+  fn formats() {
+    assert_eq!(DumpFormat::Json, DumpFormat::from_name("json").unwrap());
+    assert_eq!(DumpFormat::Ndjson, DumpFormat::from_name("ndjson").unwrap());
+    assert!(DumpFormat::from_name("xml").is_err());
+    assert_eq!(DumpFormat::Ndjson, DumpFormat::for_path("/tmp/entries.ndjson"));
+    assert_eq!(DumpFormat::Ndjson, DumpFormat::for_path("entries.jsonl"));
+    assert_eq!(DumpFormat::Json, DumpFormat::for_path("sample_entries.json"));
+  }
+}
@@ -1,14 +1,63 @@
-use generated::{EntryDetail, MarkerRequestBody, StreamsIdsResponse, SubscriptionDetail};
-use hyper;
+use config;
+use generated::{EntryDetail, FeedlyTokenRequest, MarkerRequestBody, OAuthTokenResponse,
+                StreamsIdsResponse, SubscriptionDetail, SubscriptionDetailCategory,
+                TagRequestBody};
 use hyper::Client;
 use hyper::header;
+use http::{HttpMockableClient, HyperClientWrapper, RefreshToken, check_status};
 use regex::Regex;
 use result::{FdownError, Result};
-use serde_json;
-use std::io::Read;
+use retry::RetryPolicy;
+use serde::Deserialize;
+use serde_json::{self, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+use url::percent_encoding::{PATH_SEGMENT_ENCODE_SET, QUERY_ENCODE_SET, utf8_percent_encode};
+
+pub const DEFAULT_BASE_URL: &'static str = "https://cloud.feedly.com";
+/// Exchanges authorization codes and refresh tokens for access tokens.
+pub const TOKEN_PATH: &'static str = "/v3/auth/token";
 
 lazy_static! {
-  static ref TUMBLR_REGEX: Regex = Regex::new(r"_(\d+)(\.[:alnum:]+)$").unwrap();
+  static ref IMG_SRC_REGEX: Regex =
+    Regex::new(r#"(?i)<img\s[^>]*?\bsrc\s*=\s*["']([^"']+)["']"#).unwrap();
+}
+
+define_encode_set! {
+  /// Stream ids go in the query string, and feed ids are URLs with query strings of their own.
+  pub QUERY_PARAM_ENCODE_SET = [QUERY_ENCODE_SET] | {'&', '=', '+', '%', '?'}
+}
+
+define_encode_set! {
+  /// Tag and entry ids in a path, where commas separate ids.
+  pub PATH_PARAM_ENCODE_SET = [PATH_SEGMENT_ENCODE_SET] | {','}
+}
+
+/// Something to do in Feedly to entries whose images have been stored.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EntryAction {
+  MarkAsRead,
+  Unsave,
+  /// Adds the user's tag with this label, creating it if need be.
+  Tag(String),
+  /// Removes the user's tag with this label.
+  Untag(String),
+}
+
+/// A Feedly stream to read entries from: a feed, a category, a tag, or everything.
+#[derive(Debug, PartialEq)]
+pub struct Stream {
+  pub id: String,
+  pub unread_only: bool,
+}
+
+/// The JSON that Feedly sent, kept for dumping.
+#[derive(Default)]
+struct RawResponses {
+  // By entry id.
+  entries: HashMap<String, Value>,
+  subscriptions: Vec<Value>,
 }
 
 pub type Feedly = FeedlyInternal<HyperClientWrapper>;
@@ -17,23 +66,40 @@ pub struct FeedlyInternal<T>
   where T: HttpMockableClient
 {
   userid: String,
-  token: String,
+  token: RefCell<String>,
+  refresh: Option<RefreshToken>,
+  // Where refreshed access tokens are saved, if anywhere.
+  config_file: Option<String>,
+  base_url: String,
   client: T,
+  retry: RetryPolicy,
+  // None unless raw responses are kept.
+  raw: Option<RefCell<RawResponses>>,
 }
 
 impl<T> FeedlyInternal<T>
   where T: HttpMockableClient
 {
-  pub fn new(userid: &str, token: &str) -> FeedlyInternal<HyperClientWrapper> {
-    FeedlyInternal::<HyperClientWrapper>::new_with_client(userid, token, HyperClientWrapper {})
+  pub fn new(userid: &str,
+             token: &str,
+             client: Arc<Client>)
+      -> FeedlyInternal<HyperClientWrapper> {
+    FeedlyInternal::<HyperClientWrapper>::new_with_client(userid,
+                                                          token,
+                                                          HyperClientWrapper::new(client))
   }
 
   fn new_with_client<C>(userid: &str, token: &str, client: C) -> FeedlyInternal<C>
     where C: HttpMockableClient {
     FeedlyInternal {
       userid: userid.to_string(),
-      token: token.to_string(),
+      token: RefCell::new(token.to_string()),
+      refresh: None,
+      config_file: None,
+      base_url: DEFAULT_BASE_URL.to_string(),
       client: client,
+      retry: RetryPolicy::default(),
+      raw: None,
     }
   }
 
@@ -42,132 +108,368 @@ impl<T> FeedlyInternal<T>
   }
 
   fn auth_header(&self) -> header::Authorization<String> {
-    header::Authorization(format!("OAuth {}", self.token).to_owned())
+    header::Authorization(format!("OAuth {}", self.token.borrow()).to_owned())
+  }
+
+  pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+    self.retry = retry;
+  }
+
+  /// Points the client at another server, such as a sandbox or a local stub.
+  pub fn set_base_url(&mut self, base_url: &str) {
+    self.base_url = base_url.trim_right_matches('/').to_string();
+  }
+
+  fn url(&self, path: &str) -> String {
+    format!("{}{}", self.base_url, path)
+  }
+
+  /// Lets an expired access token be replaced without asking the user to log in again.
+  pub fn set_refresh_token(&mut self, refresh: RefreshToken) {
+    self.refresh = Some(refresh);
+  }
+
+  /// Saves refreshed access tokens as `token` in `config_file`, so that later runs start
+  /// with them.
+  pub fn save_tokens_to(&mut self, config_file: &str) {
+    self.config_file = Some(config_file.to_string());
+  }
+
+  fn get(&self, url: &str, with_auth: bool) -> Result<T::R> {
+    self.authorized(with_auth, |auth_header| {
+      self.retry.run(|| check_status(url, try!(self.client.get(url, auth_header.clone()))))
+    })
+  }
+
+  fn post(&self, url: &str, with_auth: bool, body: &[u8]) -> Result<T::R> {
+    self.authorized(with_auth, |auth_header| {
+      self.retry.run(|| check_status(url, try!(self.client.post(url, auth_header.clone(), body))))
+    })
+  }
+
+  fn put(&self, url: &str, with_auth: bool, body: &[u8]) -> Result<T::R> {
+    self.authorized(with_auth, |auth_header| {
+      self.retry.run(|| check_status(url, try!(self.client.put(url, auth_header.clone(), body))))
+    })
+  }
+
+  fn delete(&self, url: &str, with_auth: bool) -> Result<T::R> {
+    self.authorized(with_auth, |auth_header| {
+      self.retry.run(|| check_status(url, try!(self.client.delete(url, auth_header.clone()))))
+    })
+  }
+
+  /// Runs `request`, and if Feedly says the access token is no good, runs it again with a
+  /// new one.
+  fn authorized<R, F>(&self, with_auth: bool, request: F) -> Result<R>
+    where F: Fn(Option<header::Authorization<String>>) -> Result<R> {
+    if !with_auth {
+      return request(None);
+    }
+    match request(Some(self.auth_header())) {
+      Err(FdownError::HttpStatus { status: 401, .. }) if self.refresh.is_some() => {}
+      result => return result,
+    }
+    try!(self.refresh_access_token());
+    request(Some(self.auth_header()))
+  }
+
+  fn refresh_access_token(&self) -> Result<()> {
+    let refresh = match self.refresh {
+      Some(ref refresh) => refresh,
+      None => return Err(FdownError::Login("no Feedly refresh token".to_string())),
+    };
+    let body = try!(serde_json::to_vec(&FeedlyTokenRequest {
+      grant_type: "refresh_token",
+      client_id: &refresh.client_id,
+      client_secret: &refresh.client_secret,
+      refresh_token: Some(&refresh.refresh_token),
+      code: None,
+      redirect_uri: None,
+    }));
+    let response = try!(self.post(&self.url(TOKEN_PATH), false, &body));
+    let tokens: OAuthTokenResponse = try!(serde_json::from_reader(response));
+    if let Some(ref config_file) = self.config_file {
+      try!(config::update_file(config_file, &[("token", &tokens.access_token)]));
+    }
+    *self.token.borrow_mut() = tokens.access_token;
+    Ok(())
+  }
+
+  /// Expands `source` into a stream to read. `saved` and `all` are the user's saved and
+  /// complete streams, and `category/<label>` and `tag/<label>` are the user's own categories
+  /// and tags. Anything else, such as `feed/http://example.com/rss` or a full `user/...` id,
+  /// is used as is.
+  pub fn stream(&self, source: &str, unread_only: bool) -> Stream {
+    let id = match source {
+      "saved" => self.saved_feed(),
+      "all" => format!("user/{}/category/global.all", self.userid),
+      _ if source.starts_with("category/") || source.starts_with("tag/") => {
+        format!("user/{}/{}", self.userid, source)
+      }
+      _ => source.to_string(),
+    };
+    Stream {
+      id: id,
+      unread_only: unread_only,
+    }
+  }
+
+  pub fn saved_stream(&self) -> Stream {
+    self.stream("saved", false)
   }
 
-  pub fn saved_entry_ids(&self, count: usize) -> Result<Vec<String>> {
-    let url = format!("http://cloud.feedly.com/v3/streams/ids?streamId={}&count={}",
-                      self.saved_feed(),
-                      count);
-    let response = try!(self.client.get(url.as_str(), Some(self.auth_header())));
+  pub fn stream_entry_ids(&self,
+                          stream: &Stream,
+                          count: usize,
+                          continuation: Option<&str>)
+      -> Result<StreamsIdsResponse> {
+    let mut url = format!("{}/v3/streams/ids?streamId={}&count={}",
+                          self.base_url,
+                          utf8_percent_encode(&stream.id, QUERY_PARAM_ENCODE_SET),
+                          count);
+    if stream.unread_only {
+      url.push_str("&unreadOnly=true");
+    }
+    if let Some(continuation) = continuation {
+      url.push_str("&continuation=");
+      url.push_str(&utf8_percent_encode(continuation, QUERY_PARAM_ENCODE_SET).to_string());
+    }
+    let response = try!(self.get(url.as_str(), true));
     let ids_response: StreamsIdsResponse = try!(serde_json::from_reader(response));
-    Ok(ids_response.ids)
+    Ok(ids_response)
+  }
+
+  /// Pages through `stream` until `count` entries have passed `filter_func`, or until the
+  /// stream runs out.
+  pub fn stream_entries(&self,
+                        stream: &Stream,
+                        count: usize,
+                        filter_func: &Fn(&EntryDetail) -> bool)
+      -> Result<Vec<EntryDetail>> {
+    let mut result: Vec<EntryDetail> = Vec::with_capacity(count);
+    let mut continuation: Option<String> = None;
+    while result.len() < count {
+      let ids_response =
+        try!(self.stream_entry_ids(stream, count, continuation.as_ref().map(|c| c.as_str())));
+      if !ids_response.ids.is_empty() {
+        let entries = try!(self.detail_for_entries(ids_response.ids));
+        result.extend(entries.into_iter().filter(|e| filter_func(e)));
+      }
+      match ids_response.continuation {
+        Some(c) => continuation = Some(c),
+        None => break,
+      }
+    }
+    result.truncate(count);
+    Ok(result)
   }
 
-  pub fn unsave_entries(&self, entries: &Vec<&EntryDetail>) -> Result<()> {
-    let url = "http://cloud.feedly.com/v3/markers";
-    let entry_ids: Vec<String> = entries.iter().map(|e| e.id.clone()).collect();
+  /// Does `action` to the entries with these ids.
+  pub fn apply(&self, action: &EntryAction, entry_ids: &[String]) -> Result<()> {
+    match *action {
+      EntryAction::MarkAsRead => self.mark_entries("markAsRead", entry_ids),
+      EntryAction::Unsave => self.mark_entries("markAsUnsaved", entry_ids),
+      EntryAction::Tag(ref label) => {
+        let body = try!(serde_json::to_vec(&TagRequestBody { entry_ids: entry_ids.to_vec() }));
+        try!(self.put(&self.tag_url(label), true, &body));
+        Ok(())
+      }
+      EntryAction::Untag(ref label) => {
+        let encoded: Vec<String> = entry_ids.iter()
+          .map(|id| utf8_percent_encode(id, PATH_PARAM_ENCODE_SET).to_string())
+          .collect();
+        try!(self.delete(&format!("{}/{}", self.tag_url(label), encoded.join(",")), true));
+        Ok(())
+      }
+    }
+  }
+
+  fn mark_entries(&self, action: &str, entry_ids: &[String]) -> Result<()> {
+    let url = self.url("/v3/markers");
     let body_struct = MarkerRequestBody {
-      action: "markAsUnsaved".to_string(),
+      action: action.to_string(),
       type_field: "entries".to_string(),
-      entry_ids: entry_ids,
+      entry_ids: entry_ids.to_vec(),
     };
     let body: Vec<u8> = try!(serde_json::to_vec(&body_struct));
-    try!(self.client.post(url, Some(self.auth_header()), body.as_slice()));
+    try!(self.post(&url, true, body.as_slice()));
     Ok(())
   }
 
+  fn tag_url(&self, label: &str) -> String {
+    let tag_id = format!("user/{}/tag/{}", self.userid, label);
+    self.url(&format!("/v3/tags/{}", utf8_percent_encode(&tag_id, PATH_PARAM_ENCODE_SET)))
+  }
+
   pub fn subscriptions(&self) -> Result<Vec<SubscriptionDetail>> {
-    let response = try!(self.client.get("http://cloud.feedly.com/v3/subscriptions",
-                                        Some(self.auth_header())));
-    let detail: Vec<SubscriptionDetail> = try!(serde_json::from_reader(response));
+    let response = try!(self.get(&self.url("/v3/subscriptions"), true));
+    let (detail, values) = try!(self.parse_array::<SubscriptionDetail>(response));
+    if let Some(ref raw) = self.raw {
+      raw.borrow_mut().subscriptions = values;
+    }
     Ok(detail)
   }
 
+  pub fn categories(&self) -> Result<Vec<SubscriptionDetailCategory>> {
+    let response = try!(self.get(&self.url("/v3/categories"), true));
+    let categories: Vec<SubscriptionDetailCategory> = try!(serde_json::from_reader(response));
+    Ok(categories)
+  }
+
   pub fn detail_for_entries(&self, ids: Vec<String>) -> Result<Vec<EntryDetail>> {
-    let url = "http://cloud.feedly.com/v3/entries/.mget";
+    let url = self.url("/v3/entries/.mget");
     let quoted: Vec<String> = ids.into_iter().map(|i| "\"".to_string() + &i + "\"").collect();
     let body = "[".to_string() + &quoted.join(",") + "]";
 
-    let response = try!(self.client.post(url, None, body.as_bytes()));
-    let detail: Vec<EntryDetail> = try!(serde_json::from_reader(response));
+    let response = try!(self.post(&url, false, body.as_bytes()));
+    let (detail, values) = try!(self.parse_array::<EntryDetail>(response));
+    if let Some(ref raw) = self.raw {
+      let mut raw = raw.borrow_mut();
+      for (entry, value) in detail.iter().zip(values) {
+        raw.entries.insert(entry.id.clone(), value);
+      }
+    }
 
     Ok(detail)
   }
 
-  pub fn extract_image_url<'a>(detail: &'a EntryDetail) -> Option<&'a String> {
-    if let Some(ref visual) = detail.visual {
-      if let Some(ref url) = visual.url {
-        return Some(url);
-      }
-    }
-    None
+  /// Keeps the JSON that Feedly sends for entries and subscriptions, for dumping as is.
+  pub fn keep_raw_responses(&mut self) {
+    self.raw = Some(RefCell::new(RawResponses::default()));
   }
 
-  pub fn tumblr_filter(url: &str) -> String {
-    return TUMBLR_REGEX.replace(url, "_1280$2");
+  /// The entry with this id as Feedly sent it, if raw responses are kept.
+  pub fn raw_entry(&self, id: &str) -> Option<Value> {
+    self.raw.as_ref().and_then(|raw| raw.borrow().entries.get(id).cloned())
   }
-}
-
-pub trait HttpMockableClient {
-  type R: Read;
 
-  fn get(&self, url: &str, authHeader: Option<header::Authorization<String>>) -> Result<Self::R>;
-  fn post(&self,
-          url: &str,
-          authHeader: Option<header::Authorization<String>>,
-          body: &[u8])
-      -> Result<Self::R>;
-}
-
-pub struct HyperClientWrapper {}
-
-impl HttpMockableClient for HyperClientWrapper {
-  type R = hyper::client::Response;
+  /// The subscriptions as Feedly last sent them, if raw responses are kept.
+  pub fn raw_subscriptions(&self) -> Vec<Value> {
+    self.raw.as_ref().map_or(Vec::new(), |raw| raw.borrow().subscriptions.clone())
+  }
 
-  fn get(&self, url: &str, auth_header: Option<header::Authorization<String>>) -> Result<Self::R> {
-    let client = Client::new();
-    let mut builder = client.get(url);
-    match auth_header {
-      Some(h) => builder = builder.header(h),
-      None => {}
+  /// Parses a JSON array. The elements are also returned as they were sent if raw responses
+  /// are kept.
+  fn parse_array<V>(&self, response: T::R) -> Result<(Vec<V>, Vec<Value>)>
+    where V: Deserialize {
+    if self.raw.is_none() {
+      return Ok((try!(serde_json::from_reader(response)), Vec::new()));
     }
-    builder.send().map_err(|e| FdownError::from(e))
+    let values: Vec<Value> = try!(serde_json::from_reader(response));
+    let mut parsed = Vec::with_capacity(values.len());
+    for value in &values {
+      parsed.push(try!(serde_json::from_value(value.clone())));
+    }
+    Ok((parsed, values))
   }
 
-  fn post(&self,
-          url: &str,
-          auth_header: Option<header::Authorization<String>>,
-          body: &[u8])
-      -> Result<Self::R> {
-    let client = Client::new();
-    let mut builder = client.post(url).body(body);
-    match auth_header {
-      Some(h) => builder = builder.header(h),
-      None => {}
+  /// Returns every unique image URL in the entry: the visual first, then any `<img>` tags
+  /// in the content and summary, then image enclosures.
+  pub fn extract_image_urls(detail: &EntryDetail) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    {
+      let mut add = |url: &str| {
+        let url = url.trim().replace("&amp;", "&");
+        if url.starts_with("http") && !urls.contains(&url) {
+          urls.push(url);
+        }
+      };
+
+      if let Some(url) = detail.visual.as_ref().and_then(|v| v.url.as_ref()) {
+        add(url);
+      }
+      let html_parts = [detail.content.as_ref(), detail.summary.as_ref()];
+      for part in html_parts.iter() {
+        if let Some(content) = part.and_then(|c| c.content.as_ref()) {
+          for cap in IMG_SRC_REGEX.captures_iter(content) {
+            if let Some(src) = cap.at(1) {
+              add(src);
+            }
+          }
+        }
+      }
+      if let Some(ref enclosures) = detail.enclosure {
+        for link in enclosures {
+          if link.type_field.as_ref().map_or(false, |t| t.starts_with("image/")) {
+            add(&link.href);
+          }
+        }
+      }
     }
-    builder.send().map_err(|e| FdownError::from(e))
+    urls
   }
 }
 
 #[cfg(test)]
 mod tests {
   use generated::*;
+  use http::RefreshToken;
   use hyper::header;
   use result::{FdownError, Result};
+  use serde_json;
   use std::cell::{Cell, RefCell};
   use std::convert::From;
-  use std::io::Cursor;
+  use std::env;
+  use std::fs::File;
+  use std::io;
+  use std::io::{Cursor, Read, Write};
+  use std::time::Duration;
   use super::*;
+  use timestamp::Timestamp;
 
   const TEST_USERID: &'static str = "test_userid";
   const TEST_TOKEN: &'static str = "test_token";
 
   type MockFeedly<'a> = FeedlyInternal<NullClient<'a>>;
 
+  struct MockResponse {
+    status: u16,
+    retry_after: Option<&'static str>,
+    body: Cursor<Vec<u8>>,
+  }
+  impl Read for MockResponse {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+      self.body.read(buf)
+    }
+  }
+  impl HttpResponse for MockResponse {
+    fn status(&self) -> u16 {
+      self.status
+    }
+    fn header(&self, name: &str) -> Option<String> {
+      match name {
+        "Retry-After" => self.retry_after.map(|v| v.to_string()),
+        _ => None,
+      }
+    }
+  }
+
   struct NullClient<'a> {
     responses: Vec<&'a str>,
+    // Status codes for the responses, by index. Missing statuses are 200.
+    statuses: Vec<u16>,
+    // Retry-After headers for the responses, by index.
+    retry_afters: Vec<Option<&'static str>>,
+    next_response: Cell<usize>,
     url: RefCell<Option<String>>,
     has_auth: Cell<bool>,
     body: RefCell<Option<Vec<u8>>>,
+    method: Cell<&'static str>,
   }
   impl<'a> NullClient<'a> {
     fn check_url(&self, url: &str) {
       assert_eq!(url, self.url.borrow().as_ref().unwrap());
     }
+    fn check_method(&self, method: &str) {
+      assert_eq!(method, self.method.get());
+    }
     fn check_has_auth(&self, val: bool) {
       assert_eq!(val, self.has_auth.get());
     }
+    fn check_requests(&self, count: usize) {
+      assert_eq!(count, self.next_response.get());
+    }
     fn check_has_no_body(&self) {
       assert_eq!(true, self.body.borrow().as_ref().is_none());
     }
@@ -179,10 +481,12 @@ mod tests {
     }
 
     fn get_or_post(&self, url: &str, auth_header: Option<header::Authorization<String>>, body: Option<&[u8]>)
-        -> Result<Vec<u8>> {
-      if self.responses.len() < 1 {
+        -> Result<MockResponse> {
+      let index = self.next_response.get();
+      if index >= self.responses.len() {
         return Err(FdownError::TestError);
       }
+      self.next_response.set(index + 1);
 
       // Save away our arguments for verification.
       *self.url.borrow_mut() = Some(url.to_string());
@@ -190,40 +494,109 @@ mod tests {
       if body.is_some() {
         *self.body.borrow_mut() = body.map(|b| Vec::from(b));
       }  
-      let bytes = self.responses.get(0).unwrap().as_bytes();
-      let vec = From::from(bytes);
-      Ok(vec)
+      let bytes = self.responses.get(index).unwrap().as_bytes();
+      Ok(MockResponse {
+        status: self.statuses.get(index).cloned().unwrap_or(200),
+        retry_after: self.retry_afters.get(index).cloned().unwrap_or(None),
+        body: Cursor::new(From::from(bytes)),
+      })
     }
   }
   impl<'a> HttpMockableClient for NullClient<'a> {
-    type R = Cursor<Vec<u8>>;
+    type R = MockResponse;
 
     fn get(&self,
            url: &str,
            auth_header: Option<header::Authorization<String>>)
         -> Result<Self::R> {
-      let r = try!(self.get_or_post(url, auth_header, None));
-      Ok(Cursor::new(r))
+      self.method.set("GET");
+      self.get_or_post(url, auth_header, None)
     }
     fn post(&self,
             url: &str,
             auth_header: Option<header::Authorization<String>>,
             body: &[u8])
         -> Result<Self::R> {
-      let r = try!(self.get_or_post(url, auth_header, Some(body)));
-      Ok(Cursor::new(r))
+      self.method.set("POST");
+      self.get_or_post(url, auth_header, Some(body))
+    }
+    fn put(&self,
+           url: &str,
+           auth_header: Option<header::Authorization<String>>,
+           body: &[u8])
+        -> Result<Self::R> {
+      self.method.set("PUT");
+      self.get_or_post(url, auth_header, Some(body))
+    }
+    fn delete(&self,
+              url: &str,
+              auth_header: Option<header::Authorization<String>>)
+        -> Result<Self::R> {
+      self.method.set("DELETE");
+      self.get_or_post(url, auth_header, None)
+    }
+    fn post_with_headers(&self,
+                         url: &str,
+                         headers: header::Headers,
+                         body: &mut Read,
+                         _len: u64)
+        -> Result<Self::R> {
+      let mut bytes = Vec::new();
+      try!(body.read_to_end(&mut bytes));
+      let auth_header = headers.get::<header::Authorization<String>>().cloned();
+      self.method.set("POST");
+      self.get_or_post(url, auth_header, Some(&bytes))
     }
   }
 
   fn null_client<'a>(responses: Vec<&'a str>) -> MockFeedly<'a> {
-    Feedly::new_with_client(TEST_USERID,
-                            TEST_TOKEN,
-                            NullClient {
-                              responses: responses,
-                              url: RefCell::new(None),
-                              has_auth: Cell::new(false),
-                              body: RefCell::new(None),
-                            })
+    null_client_with_statuses(vec![], responses)
+  }
+
+  fn null_client_with_statuses<'a>(statuses: Vec<u16>, responses: Vec<&'a str>) -> MockFeedly<'a> {
+    let mut feedly = retrying_client(statuses, vec![], responses);
+    feedly.set_retry_policy(RetryPolicy::without_sleeping(1));
+    feedly
+  }
+
+  fn retrying_client<'a>(statuses: Vec<u16>,
+                         retry_afters: Vec<Option<&'static str>>,
+                         responses: Vec<&'a str>)
+      -> MockFeedly<'a> {
+    let mut feedly = Feedly::new_with_client(TEST_USERID,
+                                             TEST_TOKEN,
+                                             NullClient {
+                                               responses: responses,
+                                               statuses: statuses,
+                                               retry_afters: retry_afters,
+                                               next_response: Cell::new(0),
+                                               url: RefCell::new(None),
+                                               has_auth: Cell::new(false),
+                                               body: RefCell::new(None),
+                                               method: Cell::new(""),
+                                             });
+    feedly.set_retry_policy(RetryPolicy::with_sleeper(3, 100, record_sleep));
+    feedly
+  }
+
+  fn test_refresh_token() -> RefreshToken {
+    RefreshToken {
+      refresh_token: "refresh".to_string(),
+      client_id: "cid".to_string(),
+      client_secret: "secret".to_string(),
+    }
+  }
+
+  thread_local! {
+    static SLEEPS: RefCell<Vec<Duration>> = RefCell::new(Vec::new());
+  }
+
+  fn record_sleep(duration: Duration) {
+    SLEEPS.with(|sleeps| sleeps.borrow_mut().push(duration));
+  }
+
+  fn recorded_sleeps() -> Vec<Duration> {
+    SLEEPS.with(|sleeps| sleeps.borrow().clone())
   }
 
   #[test]
@@ -243,26 +616,256 @@ mod tests {
     let resp = "{ \"ids\": [ \"id1\", \"id2\", \"id3\" ],
                   \"continuation\": \"continuation\" }";
     let feedly = null_client(vec![resp]);
-    let ids = feedly.saved_entry_ids(5).unwrap();
+    let ids = feedly.stream_entry_ids(&feedly.saved_stream(), 5, None).unwrap();
     feedly.client.check_has_auth(true);
     feedly.client
-      .check_url("http://cloud.feedly.com/v3/streams/ids?streamId=user/test_userid/tag/global.\
+      .check_url("https://cloud.feedly.com/v3/streams/ids?streamId=user/test_userid/tag/global.\
                   saved&count=5");
     feedly.client.check_has_no_body();
-    assert_eq!(vec!["id1", "id2", "id3"], ids);
+    assert_eq!(vec!["id1", "id2", "id3"], ids.ids);
+    assert_eq!(Some("continuation".to_string()), ids.continuation);
+  }
+
+  #[test]
+  fn saved_entry_ids_continuation() {
+    let resp = "{ \"ids\": [ \"id4\" ] }";
+    let feedly = null_client(vec![resp]);
+    let ids = feedly.stream_entry_ids(&feedly.saved_stream(), 5, Some("abc")).unwrap();
+    feedly.client
+      .check_url("https://cloud.feedly.com/v3/streams/ids?streamId=user/test_userid/tag/global.\
+                  saved&count=5&continuation=abc");
+    assert_eq!(vec!["id4"], ids.ids);
+    assert_eq!(None, ids.continuation);
   }
 
   #[test]
   fn saved_entry_ids_bad_http() {
     let feedly = null_client(vec![]);
-    feedly.saved_entry_ids(5).unwrap_err();
+    feedly.stream_entry_ids(&feedly.saved_stream(), 5, None).unwrap_err();
+  }
+
+  #[test]
+  fn saved_entry_ids_expired_token() {
+    let resp = "{ \"errorCode\": 401, \"errorId\": \"ap3int\", \"errorMessage\": \"token \
+                expired\" }";
+    let feedly = null_client_with_statuses(vec![401], vec![resp]);
+    match feedly.stream_entry_ids(&feedly.saved_stream(), 5, None) {
+      Err(FdownError::HttpStatus { status, message, .. }) => {
+        assert_eq!(401, status);
+        assert_eq!(Some("token expired".to_string()), message);
+      }
+      _ => panic!("expected an HttpStatus error"),
+    }
+  }
+
+  #[test]
+  fn refreshes_expired_token() {
+    let expired = "{ \"errorCode\": 401, \"errorMessage\": \"token expired\" }";
+    let tokens = "{ \"id\": \"test_userid\", \"access_token\": \"new_token\", \
+                  \"expires_in\": 3600 }";
+    let ids = "{ \"ids\": [ \"id1\" ] }";
+    let mut feedly = null_client_with_statuses(vec![401], vec![expired, tokens, ids]);
+    feedly.set_refresh_token(test_refresh_token());
+    assert_eq!(vec!["id1"], feedly.stream_entry_ids(&feedly.saved_stream(), 5, None).unwrap().ids);
+    feedly.client.check_requests(3);
+    feedly.client.check_has_auth(true);
+    let header::Authorization(s) = feedly.auth_header();
+    assert_eq!("OAuth new_token", s);
+  }
+
+  #[test]
+  fn refresh_request() {
+    let mut feedly = null_client(vec!["{ \"access_token\": \"new_token\" }"]);
+    feedly.set_refresh_token(test_refresh_token());
+    feedly.refresh_access_token().unwrap();
+    feedly.client.check_url("https://cloud.feedly.com/v3/auth/token");
+    feedly.client.check_has_auth(false);
+    feedly.client.check_body("{\"grant_type\":\"refresh_token\",\"client_id\":\"cid\",\
+                              \"client_secret\":\"secret\",\"refresh_token\":\"refresh\"}");
+  }
+
+  #[test]
+  fn refresh_saves_token() {
+    let path = env::temp_dir().join("fdown_feedly_refresh_config");
+    File::create(&path).unwrap().write_all(b"userid=me\ntoken=old\n").unwrap();
+    let mut feedly = null_client(vec!["{ \"access_token\": \"new_token\" }"]);
+    feedly.set_refresh_token(test_refresh_token());
+    feedly.save_tokens_to(path.to_str().unwrap());
+    feedly.refresh_access_token().unwrap();
+    let mut contents = String::new();
+    File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+    assert_eq!("userid=me\ntoken=new_token\n", contents);
+  }
+
+  #[test]
+  fn unsave_entries_server_error() {
+    let feedly = null_client_with_statuses(vec![500], vec!["oops"]);
+    match feedly.apply(&EntryAction::Unsave, &[]) {
+      Err(FdownError::HttpStatus { status, url, message, .. }) => {
+        assert_eq!(500, status);
+        assert_eq!("https://cloud.feedly.com/v3/markers", url);
+        assert_eq!(None, message);
+      }
+      _ => panic!("expected an HttpStatus error"),
+    }
+  }
+
+  fn test_entry_ids() -> Vec<String> {
+    vec!["id1".to_string(), "abc=_15:2c/f".to_string()]
+  }
+
+  #[test]
+  fn unsave() {
+    let feedly = null_client(vec![""]);
+    feedly.apply(&EntryAction::Unsave, &test_entry_ids()).unwrap();
+    feedly.client.check_method("POST");
+    feedly.client.check_url("https://cloud.feedly.com/v3/markers");
+    feedly.client.check_has_auth(true);
+    feedly.client.check_body("{\"action\":\"markAsUnsaved\",\"type\":\"entries\",\
+                              \"entryIds\":[\"id1\",\"abc=_15:2c/f\"]}");
+  }
+
+  #[test]
+  fn mark_as_read() {
+    let feedly = null_client(vec![""]);
+    feedly.apply(&EntryAction::MarkAsRead, &test_entry_ids()).unwrap();
+    feedly.client.check_method("POST");
+    feedly.client.check_url("https://cloud.feedly.com/v3/markers");
+    feedly.client.check_body("{\"action\":\"markAsRead\",\"type\":\"entries\",\
+                              \"entryIds\":[\"id1\",\"abc=_15:2c/f\"]}");
+  }
+
+  #[test]
+  fn tag() {
+    let feedly = null_client(vec![""]);
+    feedly.apply(&EntryAction::Tag("fdown/archived".to_string()), &test_entry_ids()).unwrap();
+    feedly.client.check_method("PUT");
+    feedly.client
+      .check_url("https://cloud.feedly.com/v3/tags/user%2Ftest_userid%2Ftag%2Ffdown%2Farchived");
+    feedly.client.check_has_auth(true);
+    feedly.client.check_body("{\"entryIds\":[\"id1\",\"abc=_15:2c/f\"]}");
+  }
+
+  #[test]
+  fn untag() {
+    let feedly = null_client(vec![""]);
+    feedly.apply(&EntryAction::Untag("keep, maybe".to_string()), &test_entry_ids()).unwrap();
+    feedly.client.check_method("DELETE");
+    feedly.client.check_url("https://cloud.feedly.com/v3/tags/user%2Ftest_userid%2Ftag%2Fkeep%2C\
+                             %20maybe/id1,abc=_15:2c%2Ff");
+    feedly.client.check_has_auth(true);
+    feedly.client.check_has_no_body();
+  }
+
+  #[test]
+  fn categories() {
+    let resp = "[ { \"id\": \"user/test_userid/category/tech\", \"label\": \"tech\" },
+                  { \"id\": \"user/test_userid/category/global.uncategorized\" } ]";
+    let feedly = null_client(vec![resp]);
+    let categories = feedly.categories().unwrap();
+    feedly.client.check_url("https://cloud.feedly.com/v3/categories");
+    feedly.client.check_has_auth(true);
+    assert_eq!(2, categories.len());
+    assert_eq!(Some("tech".to_string()), categories[0].label);
+    assert_eq!(None, categories[1].label);
+  }
+
+  #[test]
+  fn retries_server_errors() {
+    let resp = "{ \"ids\": [ \"id1\" ] }";
+    let feedly = retrying_client(vec![503, 500, 200], vec![], vec!["", "", resp]);
+    let ids = feedly.stream_entry_ids(&feedly.saved_stream(), 5, None).unwrap();
+    assert_eq!(vec!["id1"], ids.ids);
+    feedly.client.check_requests(3);
+    feedly.client.check_has_auth(true);
+  }
+
+  #[test]
+  fn retries_give_up() {
+    let feedly = retrying_client(vec![503, 503, 503, 200], vec![], vec!["", "", "", "[]"]);
+    match feedly.detail_for_entries(vec![]) {
+      Err(FdownError::HttpStatus { status, .. }) => assert_eq!(503, status),
+      _ => panic!("expected an HttpStatus error"),
+    }
+    feedly.client.check_requests(3);
+  }
+
+  #[test]
+  fn no_retry_on_client_error() {
+    let feedly = retrying_client(vec![401, 200], vec![], vec!["", "[]"]);
+    feedly.subscriptions().unwrap_err();
+    feedly.client.check_requests(1);
+  }
+
+  #[test]
+  fn retry_honors_retry_after() {
+    let feedly = retrying_client(vec![429, 200], vec![Some("12")], vec!["", "[]"]);
+    feedly.subscriptions().unwrap();
+    feedly.client.check_requests(2);
+    assert_eq!(vec![Duration::from_secs(12)], recorded_sleeps());
   }
 
   #[test]
   fn saved_entry_ids_bad_json() {
     let resp = "{ ids: [ \"id1\", \"id2\", \"id3\" ], \"continuation\": \"continuation\" }";
     let feedly = null_client(vec![resp]);
-    feedly.saved_entry_ids(5).unwrap_err();
+    feedly.stream_entry_ids(&feedly.saved_stream(), 5, None).unwrap_err();
+  }
+
+  #[test]
+  fn saved_entries_follows_continuation() {
+    let feedly = null_client(vec!["{ \"ids\": [ \"id1\", \"id2\" ], \"continuation\": \"c1\" }",
+                                  "[{ \"id\": \"id1\" }, { \"id\": \"id2\" }]",
+                                  "{ \"ids\": [ \"id3\", \"id4\" ], \"continuation\": \"c2\" }",
+                                  "[{ \"id\": \"id3\" }, { \"id\": \"id4\" }]"]);
+    let entries = feedly.stream_entries(&feedly.saved_stream(),
+                        2,
+                        &|e: &EntryDetail| e.id != "id1" && e.id != "id2")
+      .unwrap();
+    let ids: Vec<&str> = entries.iter().map(|e| e.id.as_str()).collect();
+    assert_eq!(vec!["id3", "id4"], ids);
+  }
+
+  #[test]
+  fn saved_entries_stream_runs_out() {
+    let feedly = null_client(vec!["{ \"ids\": [ \"id1\", \"id2\" ] }",
+                                  "[{ \"id\": \"id1\" }, { \"id\": \"id2\" }]"]);
+    let entries =
+      feedly.stream_entries(&feedly.saved_stream(), 5, &|e: &EntryDetail| e.id == "id2").unwrap();
+    assert_eq!(1, entries.len());
+    assert_eq!("id2", entries[0].id);
+  }
+
+  #[test]
+  fn saved_entries_truncates_to_count() {
+    let feedly = null_client(vec!["{ \"ids\": [ \"id1\", \"id2\", \"id3\" ], \"continuation\": \"c1\" }",
+                                  "[{ \"id\": \"id1\" }, { \"id\": \"id2\" }, { \"id\": \"id3\" }]"]);
+    let entries =
+      feedly.stream_entries(&feedly.saved_stream(), 2, &|_: &EntryDetail| true).unwrap();
+    assert_eq!(2, entries.len());
+  }
+
+  #[test]
+  fn streams() {
+    let feedly = null_client(vec![]);
+    assert_eq!("user/test_userid/tag/global.saved", feedly.stream("saved", false).id);
+    assert_eq!("user/test_userid/category/global.all", feedly.stream("all", true).id);
+    assert_eq!("user/test_userid/category/Photos", feedly.stream("category/Photos", false).id);
+    assert_eq!("user/test_userid/tag/keep", feedly.stream("tag/keep", false).id);
+    assert_eq!("feed/http://example.com/rss",
+               feedly.stream("feed/http://example.com/rss", false).id);
+    assert_eq!("user/other/category/x", feedly.stream("user/other/category/x", false).id);
+    assert!(feedly.stream("all", true).unread_only);
+  }
+
+  #[test]
+  fn stream_entry_ids_feed() {
+    let feedly = null_client(vec!["{ \"ids\": [] }"]);
+    let stream = feedly.stream("feed/http://example.com/rss?a=1&b=2", true);
+    feedly.stream_entry_ids(&stream, 5, Some("c+1")).unwrap();
+    feedly.client
+      .check_url("https://cloud.feedly.com/v3/streams/ids?streamId=feed/http://example.com/\
+                  rss%3Fa%3D1%26b%3D2&count=5&unreadOnly=true&continuation=c%2B1");
   }
 
   #[test]
@@ -270,7 +873,7 @@ mod tests {
     let feedly = null_client(vec!["[]"]);
     let entries = feedly.detail_for_entries(vec![]).unwrap();
     feedly.client.check_has_auth(false);
-    feedly.client.check_url("http://cloud.feedly.com/v3/entries/.mget");
+    feedly.client.check_url("https://cloud.feedly.com/v3/entries/.mget");
     feedly.client.check_body("[]");
     assert_eq!(0, entries.len());
   }
@@ -282,32 +885,222 @@ mod tests {
     let entries =
       feedly.detail_for_entries(vec!["id1".to_string(), "id2".to_string(), "id3".to_string()])
         .unwrap();
-    feedly.client.check_url("http://cloud.feedly.com/v3/entries/.mget");
+    feedly.client.check_url("https://cloud.feedly.com/v3/entries/.mget");
     feedly.client.check_has_auth(false);
     feedly.client.check_body("[\"id1\",\"id2\",\"id3\"]");
 
     assert_eq!(3, entries.len());
-    let foo: Vec<EntryDetail> = vec![EntryDetail {
-                                       id: "id1".to_string(),
-                                       fingerprint: None,
-                                       origin: None,
-                                       visual: None,
-                                     },
-                                     EntryDetail {
-                                       id: "id2".to_string(),
-                                       fingerprint: None,
-                                       origin: None,
-                                       visual: None,
-                                     },
-                                     EntryDetail {
-                                       id: "id3".to_string(),
-                                       fingerprint: None,
-                                       origin: None,
-                                       visual: None,
-                                     }];
+    let foo: Vec<EntryDetail> = vec![EntryDetail { id: "id1".to_string(), ..Default::default() },
+                                     EntryDetail { id: "id2".to_string(), ..Default::default() },
+                                     EntryDetail { id: "id3".to_string(), ..Default::default() }];
     assert_eq!(foo, entries);
   }
 
+  fn entry_with(visual: Option<&str>, summary: Option<&str>, content: Option<&str>) -> EntryDetail {
+    EntryDetail {
+      id: "id".to_string(),
+      visual: visual.map(|url| {
+        EntryDetailVisual { url: Some(url.to_string()), ..Default::default() }
+      }),
+      summary: summary.map(|html| {
+        EntryDetailContent {
+          content: Some(html.to_string()),
+          direction: None,
+        }
+      }),
+      content: content.map(|html| {
+        EntryDetailContent {
+          content: Some(html.to_string()),
+          direction: None,
+        }
+      }),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn extract_image_urls_visual_only() {
+    let entry = entry_with(Some("http://a.com/1.jpg"), None, None);
+    assert_eq!(vec!["http://a.com/1.jpg"], MockFeedly::extract_image_urls(&entry));
+
+    let entry = entry_with(Some("none"), None, None);
+    assert_eq!(Vec::<String>::new(), MockFeedly::extract_image_urls(&entry));
+  }
+
+  #[test]
+  fn extract_image_urls_photoset() {
+    let entry = entry_with(Some("http://a.com/1.jpg"),
+                           Some("<img src=\"http://a.com/1.jpg\"><br><IMG alt='x' \
+                                 SRC='http://a.com/2.jpg?a=1&amp;b=2'>"),
+                           Some("<p><img class=\"big\" src=\"http://a.com/3.jpg\" /></p>\
+                                 <img src=\"data:image/gif;base64,R0lGOD\">"));
+    assert_eq!(vec!["http://a.com/1.jpg", "http://a.com/3.jpg", "http://a.com/2.jpg?a=1&b=2"],
+               MockFeedly::extract_image_urls(&entry));
+  }
+
+  #[test]
+  fn extract_image_urls_enclosures() {
+    let mut entry = entry_with(None, None, None);
+    entry.enclosure = Some(vec![EntryDetailLink {
+                                  href: "http://a.com/1.png".to_string(),
+                                  type_field: Some("image/png".to_string()),
+                                  length: None,
+                                },
+                                EntryDetailLink {
+                                  href: "http://a.com/1.mp3".to_string(),
+                                  type_field: Some("audio/mpeg".to_string()),
+                                  length: Some(1234),
+                                }]);
+    assert_eq!(vec!["http://a.com/1.png"], MockFeedly::extract_image_urls(&entry));
+  }
+
+  #[test]
+  fn extract_image_urls_sample_entries() {
+    let entries: Vec<EntryDetail> =
+      serde_json::from_str(include_str!("../sample_entries.json")).unwrap();
+    assert_eq!(20, entries.len());
+    for entry in &entries {
+      let urls = MockFeedly::extract_image_urls(entry);
+      assert!(!urls.is_empty());
+      assert_eq!(entry.visual.as_ref().unwrap().url.as_ref().unwrap(), &urls[0]);
+    }
+  }
+
+  fn sample_entries() -> Vec<EntryDetail> {
+    serde_json::from_str(include_str!("../sample_entries.json")).unwrap()
+  }
+
+  fn sample_subs() -> Vec<SubscriptionDetail> {
+    serde_json::from_str(include_str!("../sample_subs.json")).unwrap()
+  }
+
+  #[test]
+  fn sample_entry_fields() {
+    let entries = sample_entries();
+    let entry = &entries[0];
+    assert_eq!("cfBX1FTyBgWMD47LB+mDBO8xvSRPMYW+Yf70hpffjGI=_156bd0d2737:20fa60f:45cbc242",
+               entry.id);
+    assert_eq!(Some("http://letswatchgirls.tumblr.com/post/149412435635".to_string()),
+               entry.origin_id);
+    assert_eq!(Some("78c87c86".to_string()), entry.fingerprint);
+    assert_eq!(Some("letswatchgirls:\n\nDonika xAlexei Bazdarev".to_string()), entry.title);
+    assert_eq!(Some(Timestamp(1472041478000)), entry.published);
+    assert_eq!(Some(Timestamp(1472050571063)), entry.crawled);
+    assert_eq!(Some(true), entry.unread);
+    assert_eq!(None, entry.author);
+    assert_eq!(None, entry.content);
+
+    let origin = entry.origin.as_ref().unwrap();
+    assert_eq!("feed/http://letswatchgirls.tumblr.com/rss", origin.stream_id);
+    assert_eq!(Some("Let's Watch Girls".to_string()), origin.title);
+    assert_eq!(Some("http://letswatchgirls.tumblr.com/".to_string()), origin.html_url);
+
+    let visual = entry.visual.as_ref().unwrap();
+    assert_eq!((Some(500), Some(750)), (visual.width, visual.height));
+    assert_eq!(Some("image/jpeg".to_string()), visual.content_type);
+    assert_eq!(Some("feedly-nikon-v3.1".to_string()), visual.processor);
+
+    assert_eq!(Some(vec![EntryDetailLink {
+                           href: "http://letswatchgirls.tumblr.com/post/149412435635".to_string(),
+                           type_field: Some("text/html".to_string()),
+                           length: None,
+                         }]),
+               entry.alternate);
+    let summary = entry.summary.as_ref().unwrap();
+    assert_eq!(Some("ltr".to_string()), summary.direction);
+    assert!(summary.content.as_ref().unwrap().starts_with("<img src=\"http://67.media.tumblr"));
+  }
+
+  #[test]
+  fn sample_entry_engagement() {
+    let entries = sample_entries();
+    let entry = &entries[9];
+    assert_eq!(Some("Rob Beschizza".to_string()), entry.author);
+    assert_eq!(Some(4584), entry.engagement);
+    assert_eq!(Some(vec!["Post".to_string(),
+                         "dune".to_string(),
+                         "emoji".to_string(),
+                         "the tweeter must awaken".to_string(),
+                         "twitter".to_string()]),
+               entry.keywords);
+    assert!(entry.content.as_ref().unwrap().content.is_some());
+    assert!(entry.webfeeds.as_ref().unwrap().logo.is_some());
+    assert_eq!(Some(Timestamp(1471359100000)), entries[10].updated);
+    assert_eq!(Some(Timestamp(1471370633323)), entries[11].recrawled);
+  }
+
+  #[test]
+  fn sample_entries_all_parse() {
+    let entries = sample_entries();
+    assert_eq!(20, entries.len());
+    for entry in &entries {
+      assert!(entry.title.is_some());
+      assert!(entry.published.is_some());
+      assert!(entry.crawled.is_some());
+      assert_eq!(1, entry.alternate.as_ref().unwrap().len());
+    }
+    assert_eq!(5, entries.iter().filter(|e| e.author.is_some()).count());
+    assert_eq!(6, entries.iter().filter(|e| e.keywords.is_some()).count());
+    assert_eq!(5, entries.iter().filter(|e| e.engagement.is_some()).count());
+    assert_eq!(5, entries.iter().filter(|e| e.content.is_some()).count());
+  }
+
+  #[test]
+  fn sample_subscriptions() {
+    let subs = sample_subs();
+    assert_eq!(61, subs.len());
+
+    let sub = &subs[0];
+    assert_eq!("feed/http://feeds.feedburner.com/7Fetishes", sub.id);
+    assert_eq!(Some("7 Fetishes".to_string()), sub.title);
+    assert_eq!(Some("http://www.7fetishes.com/".to_string()), sub.website);
+    assert_eq!(Some("dead".to_string()), sub.state);
+    assert_eq!(Some(4), sub.subscribers);
+    assert_eq!(Some(0.0), sub.velocity);
+    assert_eq!(Some(Timestamp(1372762674689)), sub.updated);
+    assert_eq!(1, sub.categories.len());
+    assert_eq!("user/cceea348-110d-4606-92d0-8480ea8c8b10/category/Porn",
+               sub.categories[0].id);
+    assert_eq!(Some("Porn".to_string()), sub.categories[0].label);
+
+    let sub = &subs[1];
+    assert!(sub.categories.is_empty());
+    assert_eq!(Some(false), sub.partial);
+    assert_eq!(Some("article".to_string()), sub.content_type);
+    assert!(sub.icon_url.as_ref().unwrap().starts_with("http://storage.googleapis.com/"));
+    assert!(sub.visual_url.is_some());
+
+    assert_eq!(Some(1.2), subs[2].velocity);
+    assert_eq!(24, subs.iter().filter(|s| !s.categories.is_empty()).count());
+    assert_eq!(49, subs.iter().filter(|s| s.updated.is_some()).count());
+  }
+
+  #[test]
+  fn raw_responses() {
+    let entries = "[{ \"id\": \"id1\", \"unknown\": [1, 2] }, { \"id\": \"id2\" }]";
+    let subs = "[{ \"id\": \"feed/a\", \"categories\": [], \"wordmark\": \"w\" }]";
+    let mut feedly = null_client(vec![entries, subs]);
+    feedly.keep_raw_responses();
+    let detail = feedly.detail_for_entries(vec!["id1".to_string(), "id2".to_string()]).unwrap();
+    assert_eq!(2, detail.len());
+    let raw = feedly.raw_entry("id1").unwrap();
+    assert_eq!("[1,2]", serde_json::to_string(raw.find("unknown").unwrap()).unwrap());
+    assert!(feedly.raw_entry("id3").is_none());
+
+    assert_eq!("feed/a", feedly.subscriptions().unwrap()[0].id);
+    let raw = feedly.raw_subscriptions();
+    assert_eq!(1, raw.len());
+    assert_eq!(Some("w"), raw[0].find("wordmark").and_then(|w| w.as_str()));
+  }
+
+  #[test]
+  fn raw_responses_not_kept() {
+    let feedly = null_client(vec!["[{ \"id\": \"id1\" }]"]);
+    feedly.detail_for_entries(vec!["id1".to_string()]).unwrap();
+    assert!(feedly.raw_entry("id1").is_none());
+    assert!(feedly.raw_subscriptions().is_empty());
+  }
+
   #[test]
   fn entry_detail_bad_http() {
     let feedly = null_client(vec![]);
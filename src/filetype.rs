@@ -0,0 +1,158 @@
+use std::cmp;
+
+/// The kinds of files fdown knows how to recognize.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileType {
+  Jpeg,
+  Png,
+  Gif,
+  WebP,
+  Mp4,
+  Html,
+}
+
+impl FileType {
+  pub fn extension(&self) -> &'static str {
+    match *self {
+      FileType::Jpeg => "jpg",
+      FileType::Png => "png",
+      FileType::Gif => "gif",
+      FileType::WebP => "webp",
+      FileType::Mp4 => "mp4",
+      FileType::Html => "html",
+    }
+  }
+
+  /// Recognizes a file from its magic bytes.
+  pub fn from_bytes(bytes: &[u8]) -> Option<FileType> {
+    if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+      return Some(FileType::Jpeg);
+    }
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+      return Some(FileType::Png);
+    }
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+      return Some(FileType::Gif);
+    }
+    if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+      return Some(FileType::WebP);
+    }
+    if bytes.len() >= 8 && &bytes[4..8] == b"ftyp" {
+      return Some(FileType::Mp4);
+    }
+    if looks_like_html(bytes) {
+      return Some(FileType::Html);
+    }
+    None
+  }
+
+  /// Recognizes a MIME type, ignoring any parameters.
+  pub fn from_mime(mime: &str) -> Option<FileType> {
+    let essence = mime.split(';').next().unwrap_or("").trim().to_lowercase();
+    match essence.as_str() {
+      "image/jpeg" | "image/jpg" | "image/pjpeg" => Some(FileType::Jpeg),
+      "image/png" => Some(FileType::Png),
+      "image/gif" => Some(FileType::Gif),
+      "image/webp" => Some(FileType::WebP),
+      "video/mp4" => Some(FileType::Mp4),
+      "text/html" | "application/xhtml+xml" => Some(FileType::Html),
+      _ => None,
+    }
+  }
+
+  fn from_extension(extension: &str) -> Option<FileType> {
+    match extension.to_lowercase().as_str() {
+      "jpg" | "jpeg" | "jpe" => Some(FileType::Jpeg),
+      "png" => Some(FileType::Png),
+      "gif" => Some(FileType::Gif),
+      "webp" => Some(FileType::WebP),
+      "mp4" | "m4v" => Some(FileType::Mp4),
+      "html" | "htm" => Some(FileType::Html),
+      _ => None,
+    }
+  }
+}
+
+/// Works out what a download is: first from its bytes, then from the response's Content-Type,
+/// then from the type Feedly reported for the entry's visual.
+pub fn detect(bytes: &[u8],
+              content_type: Option<&str>,
+              feedly_content_type: Option<&str>)
+    -> Option<FileType> {
+  FileType::from_bytes(bytes)
+    .or_else(|| content_type.and_then(FileType::from_mime))
+    .or_else(|| feedly_content_type.and_then(FileType::from_mime))
+}
+
+/// Gives `filename` the extension for `file_type`, replacing an extension that disagrees.
+pub fn fix_extension(filename: &str, file_type: FileType) -> String {
+  let stem = match filename.rfind('.') {
+    Some(dot) if dot > 0 => {
+      if FileType::from_extension(&filename[dot + 1..]) == Some(file_type) {
+        return filename.to_string();
+      }
+      // Only replace things that look like extensions, so "photo.2016" keeps its date.
+      if FileType::from_extension(&filename[dot + 1..]).is_some() {
+        &filename[..dot]
+      } else {
+        filename
+      }
+    }
+    _ => filename,
+  };
+  format!("{}.{}", stem, file_type.extension())
+}
+
+fn looks_like_html(bytes: &[u8]) -> bool {
+  let head = String::from_utf8_lossy(&bytes[..cmp::min(bytes.len(), 512)]);
+  let head = head.trim_left_matches('\u{feff}').trim_left().to_lowercase();
+  head.starts_with("<!doctype html") || head.starts_with("<html") ||
+  head.starts_with("<head") || head.starts_with("<body")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn magic_bytes() {
+    assert_eq!(Some(FileType::Jpeg), FileType::from_bytes(&[0xff, 0xd8, 0xff, 0xe0, 0, 0x10]));
+    assert_eq!(Some(FileType::Png), FileType::from_bytes(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+    assert_eq!(Some(FileType::Gif), FileType::from_bytes(b"GIF89a\x01\x00"));
+    assert_eq!(Some(FileType::WebP), FileType::from_bytes(b"RIFF\x24\0\0\0WEBPVP8 "));
+    assert_eq!(Some(FileType::Mp4), FileType::from_bytes(b"\0\0\0\x18ftypmp42"));
+    assert_eq!(Some(FileType::Html),
+               FileType::from_bytes(b"\n  <!DOCTYPE html><html><body>Not Found"));
+    assert_eq!(Some(FileType::Html), FileType::from_bytes(b"<HTML>"));
+    assert_eq!(None, FileType::from_bytes(b"RIFF\x24\0\0\0WAVEfmt "));
+    assert_eq!(None, FileType::from_bytes(b""));
+  }
+
+  #[test]
+  fn mime_types() {
+    assert_eq!(Some(FileType::Jpeg), FileType::from_mime("image/jpeg"));
+    assert_eq!(Some(FileType::Png), FileType::from_mime("Image/PNG"));
+    assert_eq!(Some(FileType::Html), FileType::from_mime("text/html; charset=utf-8"));
+    assert_eq!(None, FileType::from_mime("application/octet-stream"));
+  }
+
+  #[test]
+  fn detect_order() {
+    let gif = b"GIF89a";
+    assert_eq!(Some(FileType::Gif), detect(gif, Some("image/jpeg"), Some("image/png")));
+    assert_eq!(Some(FileType::Jpeg), detect(b"????", Some("image/jpeg"), Some("image/png")));
+    assert_eq!(Some(FileType::Png),
+               detect(b"????", Some("application/octet-stream"), Some("image/png")));
+    assert_eq!(None, detect(b"????", None, None));
+  }
+
+  #[test]
+  fn extensions() {
+    assert_eq!("foo.jpg", fix_extension("foo.jpg", FileType::Jpeg));
+    assert_eq!("foo.JPEG", fix_extension("foo.JPEG", FileType::Jpeg));
+    assert_eq!("foo.gif", fix_extension("foo.jpg", FileType::Gif));
+    assert_eq!("foo.png", fix_extension("foo", FileType::Png));
+    assert_eq!("photo.2016.webp", fix_extension("photo.2016", FileType::WebP));
+    assert_eq!(".hidden.jpg", fix_extension(".hidden", FileType::Jpeg));
+  }
+}
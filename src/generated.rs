@@ -1 +1,3 @@
+use timestamp::Timestamp;
+
 include!(concat!(env!("OUT_DIR"), "/serde_types.rs"));
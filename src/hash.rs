@@ -0,0 +1,105 @@
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+use std::io::{self, Read};
+
+/// Dropbox hashes files in blocks of this size.
+const DROPBOX_BLOCK_SIZE: usize = 4 * 1024 * 1024;
+
+/// Hashes of a file's contents, for spotting copies we already have.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContentHashes {
+  /// SHA-256 of the whole file, in hex.
+  pub sha256: String,
+  /// Dropbox's `content_hash`, in hex, for comparing with files already in Dropbox.
+  pub dropbox: String,
+}
+
+impl ContentHashes {
+  /// Hashes everything `reader` yields, one Dropbox block at a time. Dropbox's hash is the
+  /// SHA-256 of the concatenated SHA-256s of each 4 MB block. See
+  /// https://www.dropbox.com/developers/reference/content-hash
+  pub fn from_reader<R>(mut reader: R) -> io::Result<ContentHashes>
+    where R: Read {
+    let mut whole = Sha256::new();
+    let mut blocks = Sha256::new();
+    let mut block = Vec::with_capacity(DROPBOX_BLOCK_SIZE);
+    let mut block_digest = [0u8; 32];
+    loop {
+      block.clear();
+      try!(reader.by_ref().take(DROPBOX_BLOCK_SIZE as u64).read_to_end(&mut block));
+      if block.is_empty() {
+        break;
+      }
+      whole.input(&block);
+      let mut hasher = Sha256::new();
+      hasher.input(&block);
+      hasher.result(&mut block_digest);
+      blocks.input(&block_digest);
+    }
+    Ok(ContentHashes {
+      sha256: whole.result_str(),
+      dropbox: blocks.result_str(),
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crypto::digest::Digest;
+  use crypto::sha2::Sha256;
+  use super::*;
+
+  // Hash whole slices at once, to check the streaming hashes against.
+  fn sha256_hex(bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input(bytes);
+    hasher.result_str()
+  }
+
+  fn dropbox_content_hash(bytes: &[u8]) -> String {
+    let mut overall = Sha256::new();
+    let mut block_digest = [0u8; 32];
+    for block in bytes.chunks(DROPBOX_BLOCK_SIZE) {
+      let mut hasher = Sha256::new();
+      hasher.input(block);
+      hasher.result(&mut block_digest);
+      overall.input(&block_digest);
+    }
+    overall.result_str()
+  }
+
+  #[test]
+  fn sha256() {
+    assert_eq!("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+               ContentHashes::from_reader(&b"abc"[..]).unwrap().sha256);
+  }
+
+  #[test]
+  fn dropbox_small() {
+    assert_eq!("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+               dropbox_content_hash(b""));
+    assert_eq!("4f8b42c22dd3729b519ba6f68d2da7cc5b2d606d05daed5ad5128cc03e6c6358",
+               dropbox_content_hash(b"abc"));
+  }
+
+  #[test]
+  fn dropbox_multiple_blocks() {
+    let mut bytes = vec![b'a'; DROPBOX_BLOCK_SIZE];
+    bytes.push(b'b');
+    assert_eq!("565546ad93383e225e7cf808fb4d527a54dec54826a5c34a24c1f19a03c62583",
+               dropbox_content_hash(&bytes));
+  }
+
+  #[test]
+  fn content_hashes() {
+    let hashes = ContentHashes::from_reader(&b"abc"[..]).unwrap();
+    assert_eq!(sha256_hex(b"abc"), hashes.sha256);
+    assert_eq!(dropbox_content_hash(b"abc"), hashes.dropbox);
+
+    let mut bytes = vec![b'a'; DROPBOX_BLOCK_SIZE];
+    bytes.push(b'b');
+    let hashes = ContentHashes::from_reader(&bytes[..]).unwrap();
+    assert_eq!(sha256_hex(&bytes), hashes.sha256);
+    assert_eq!(dropbox_content_hash(&bytes), hashes.dropbox);
+  }
+}
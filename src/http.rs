@@ -0,0 +1,286 @@
+use generated::ApiErrorBody;
+use hyper;
+use hyper::Client;
+use hyper::client::Body;
+use hyper::header::{self, Headers};
+use result::{FdownError, Result};
+use serde_json;
+use std::io::Read;
+use std::sync::Arc;
+use url::form_urlencoded;
+
+/// The parts of an HTTP response that fdown cares about, so that tests can supply their own.
+pub trait HttpResponse: Read {
+  fn status(&self) -> u16;
+  fn header(&self, name: &str) -> Option<String>;
+}
+
+impl HttpResponse for hyper::client::Response {
+  fn status(&self) -> u16 {
+    self.status.to_u16()
+  }
+
+  fn header(&self, name: &str) -> Option<String> {
+    self.headers
+      .get_raw(name)
+      .and_then(|values| values.first())
+      .and_then(|value| String::from_utf8(value.clone()).ok())
+  }
+}
+
+/// The requests that fdown makes, so that tests can supply their own client.
+pub trait HttpMockableClient {
+  type R: HttpResponse;
+
+  fn get(&self, url: &str, authHeader: Option<header::Authorization<String>>) -> Result<Self::R>;
+  fn post(&self,
+          url: &str,
+          authHeader: Option<header::Authorization<String>>,
+          body: &[u8])
+      -> Result<Self::R>;
+  fn put(&self,
+         url: &str,
+         authHeader: Option<header::Authorization<String>>,
+         body: &[u8])
+      -> Result<Self::R>;
+  fn delete(&self, url: &str, authHeader: Option<header::Authorization<String>>)
+      -> Result<Self::R>;
+  /// POSTs `len` bytes read from `body`, with `headers`, which carry any authorization.
+  fn post_with_headers(&self,
+                       url: &str,
+                       headers: Headers,
+                       body: &mut Read,
+                       len: u64)
+      -> Result<Self::R>;
+}
+
+pub struct HyperClientWrapper {
+  client: Arc<Client>,
+}
+
+impl HyperClientWrapper {
+  pub fn new(client: Arc<Client>) -> HyperClientWrapper {
+    HyperClientWrapper { client: client }
+  }
+}
+
+impl HttpMockableClient for HyperClientWrapper {
+  type R = hyper::client::Response;
+
+  fn get(&self, url: &str, auth_header: Option<header::Authorization<String>>) -> Result<Self::R> {
+    let mut builder = self.client.get(url);
+    match auth_header {
+      Some(h) => builder = builder.header(h),
+      None => {}
+    }
+    builder.send().map_err(|e| FdownError::from(e))
+  }
+
+  fn post(&self,
+          url: &str,
+          auth_header: Option<header::Authorization<String>>,
+          body: &[u8])
+      -> Result<Self::R> {
+    let mut builder = self.client.post(url).body(body);
+    match auth_header {
+      Some(h) => builder = builder.header(h),
+      None => {}
+    }
+    builder.send().map_err(|e| FdownError::from(e))
+  }
+
+  fn put(&self,
+         url: &str,
+         auth_header: Option<header::Authorization<String>>,
+         body: &[u8])
+      -> Result<Self::R> {
+    let mut builder = self.client.put(url).body(body);
+    match auth_header {
+      Some(h) => builder = builder.header(h),
+      None => {}
+    }
+    builder.send().map_err(|e| FdownError::from(e))
+  }
+
+  fn delete(&self,
+            url: &str,
+            auth_header: Option<header::Authorization<String>>)
+      -> Result<Self::R> {
+    let mut builder = self.client.delete(url);
+    match auth_header {
+      Some(h) => builder = builder.header(h),
+      None => {}
+    }
+    builder.send().map_err(|e| FdownError::from(e))
+  }
+
+  fn post_with_headers(&self,
+                       url: &str,
+                       headers: Headers,
+                       body: &mut Read,
+                       len: u64)
+      -> Result<Self::R> {
+    self.client
+      .post(url)
+      .headers(headers)
+      .body(Body::SizedBody(body, len))
+      .send()
+      .map_err(|e| FdownError::from(e))
+  }
+}
+
+/// Passes `response` through if it succeeded. Otherwise, returns an `HttpStatus` error
+/// carrying the error message from the body when Feedly or Dropbox supplied one.
+pub fn check_status<R>(url: &str, mut response: R) -> Result<R>
+  where R: HttpResponse {
+  let status = response.status();
+  if status >= 200 && status < 300 {
+    return Ok(response);
+  }
+
+  // Only the delay-seconds form of Retry-After is supported.
+  let retry_after = response.header("Retry-After").and_then(|v| v.trim().parse::<u64>().ok());
+  let mut body = String::new();
+  let _ = response.read_to_string(&mut body);
+  Err(FdownError::HttpStatus {
+    status: status,
+    url: url.to_string(),
+    message: error_message(&body),
+    retry_after: retry_after,
+  })
+}
+
+fn error_message(body: &str) -> Option<String> {
+  serde_json::from_str::<ApiErrorBody>(body)
+    .ok()
+    .and_then(|b| b.error_message.or(b.error_summary).or(b.error_description))
+}
+
+/// What an OAuth server needs to issue a new access token when the current one expires.
+pub struct RefreshToken {
+  pub refresh_token: String,
+  pub client_id: String,
+  pub client_secret: String,
+}
+
+/// Encodes `pairs` as an application/x-www-form-urlencoded body.
+pub fn form_body(pairs: &[(&str, &str)]) -> String {
+  let mut serializer = form_urlencoded::Serializer::new(String::new());
+  for &(name, value) in pairs {
+    serializer.append_pair(name, value);
+  }
+  serializer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+  use result::FdownError;
+  use std::io::{Cursor, Read};
+  use std::io;
+  use super::*;
+
+  struct TestResponse {
+    status: u16,
+    retry_after: Option<&'static str>,
+    body: Cursor<Vec<u8>>,
+  }
+
+  impl Read for TestResponse {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+      self.body.read(buf)
+    }
+  }
+
+  impl HttpResponse for TestResponse {
+    fn status(&self) -> u16 {
+      self.status
+    }
+
+    fn header(&self, name: &str) -> Option<String> {
+      match name {
+        "Retry-After" => self.retry_after.map(|v| v.to_string()),
+        _ => None,
+      }
+    }
+  }
+
+  fn response(status: u16, body: &str) -> TestResponse {
+    TestResponse {
+      status: status,
+      retry_after: None,
+      body: Cursor::new(body.as_bytes().to_vec()),
+    }
+  }
+
+  fn check_error(status: u16, body: &str) -> (u16, String, Option<String>) {
+    match check_status("http://example.com/", response(status, body)) {
+      Err(FdownError::HttpStatus { status, url, message, .. }) => (status, url, message),
+      other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+  }
+
+  #[test]
+  fn success_passes_through() {
+    let mut r = check_status("http://example.com/", response(200, "hello")).unwrap();
+    let mut body = String::new();
+    r.read_to_string(&mut body).unwrap();
+    assert_eq!("hello", body);
+
+    check_status("http://example.com/", response(204, "")).unwrap();
+  }
+
+  #[test]
+  fn feedly_error() {
+    let body = "{\"errorCode\":401,\"errorId\":\"ap3int\",\"errorMessage\":\"token expired\"}";
+    assert_eq!((401, "http://example.com/".to_string(), Some("token expired".to_string())),
+               check_error(401, body));
+  }
+
+  #[test]
+  fn dropbox_error() {
+    let body = "{\"error_summary\": \"path/conflict/file/..\", \"error\": {\".tag\": \"path\"}}";
+    assert_eq!((409,
+                "http://example.com/".to_string(),
+                Some("path/conflict/file/..".to_string())),
+               check_error(409, body));
+  }
+
+  #[test]
+  fn oauth_error() {
+    let body = "{\"error\": \"invalid_grant\", \
+                \"error_description\": \"refresh token is malformed\"}";
+    assert_eq!((400,
+                "http://example.com/".to_string(),
+                Some("refresh token is malformed".to_string())),
+               check_error(400, body));
+  }
+
+  #[test]
+  fn form() {
+    assert_eq!("grant_type=refresh_token&code=a%2Fb+c%26d",
+               form_body(&[("grant_type", "refresh_token"), ("code", "a/b c&d")]));
+  }
+
+  #[test]
+  fn retry_after() {
+    let mut r = response(429, "");
+    r.retry_after = Some(" 30 ");
+    match check_status("http://example.com/", r) {
+      Err(FdownError::HttpStatus { retry_after, .. }) => assert_eq!(Some(30), retry_after),
+      _ => panic!("expected an HttpStatus error"),
+    }
+
+    let mut r = response(503, "");
+    r.retry_after = Some("Fri, 31 Dec 1999 23:59:59 GMT");
+    match check_status("http://example.com/", r) {
+      Err(FdownError::HttpStatus { retry_after, .. }) => assert_eq!(None, retry_after),
+      _ => panic!("expected an HttpStatus error"),
+    }
+  }
+
+  #[test]
+  fn non_json_error() {
+    assert_eq!((404, "http://example.com/".to_string(), None),
+               check_error(404, "<html>Not Found</html>"));
+  }
+}
@@ -0,0 +1,259 @@
+use generated::{EntryDetail, LedgerRecord};
+use result::{FdownError, Result};
+use serde_json;
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_LEDGER: &'static str = "~/.fdown_ledger";
+
+/// An append-only JSON-lines record of every image that has been stored, so that reruns
+/// skip work that is already done.
+pub struct Ledger {
+  path: Option<PathBuf>,
+  records: Vec<LedgerRecord>,
+  entry_ids: HashSet<String>,
+  fingerprints: HashSet<String>,
+  urls: HashSet<String>,
+  // sha256 -> path
+  hashes: HashMap<String, String>,
+  // Paths that images were written to and that have not been replaced since.
+  live_paths: HashSet<String>,
+  // Paths whose images were replaced by larger copies and removed. Records are appended in
+  // entry order, so a replacement may come before the record of what it replaced.
+  replaced: HashSet<String>,
+}
+
+impl Ledger {
+  pub fn open<P>(path: P) -> Result<Ledger>
+    where P: AsRef<Path> {
+    let path = path.as_ref();
+    let mut ledger = match File::open(path) {
+      Ok(f) => try!(Ledger::new_with_bufread(BufReader::new(f))),
+      Err(ref e) if e.kind() == ErrorKind::NotFound => Ledger::empty(),
+      Err(e) => return Err(FdownError::from(e)),
+    };
+    ledger.path = Some(path.to_path_buf());
+    Ok(ledger)
+  }
+
+  fn empty() -> Ledger {
+    Ledger {
+      path: None,
+      records: Vec::new(),
+      entry_ids: HashSet::new(),
+      fingerprints: HashSet::new(),
+      urls: HashSet::new(),
+      hashes: HashMap::new(),
+      live_paths: HashSet::new(),
+      replaced: HashSet::new(),
+    }
+  }
+
+  fn new_with_bufread<T>(reader: T) -> Result<Ledger>
+    where T: BufRead {
+    let mut ledger = Ledger::empty();
+    for (i, line) in reader.lines().enumerate() {
+      let line = try!(line);
+      if line.trim().is_empty() {
+        continue;
+      }
+      let record: LedgerRecord = try!(serde_json::from_str(&line).map_err(|e| {
+        FdownError::BadFormat(format!("bad ledger record on line {}: {:?}", i + 1, e))
+      }));
+      ledger.remember(record);
+    }
+    Ok(ledger)
+  }
+
+  fn remember(&mut self, record: LedgerRecord) {
+    self.entry_ids.insert(record.entry_id.clone());
+    if let Some(ref fingerprint) = record.fingerprint {
+      self.fingerprints.insert(fingerprint.clone());
+    }
+    self.urls.insert(record.url.clone());
+    if let Some(ref replaced) = record.replaces {
+      self.replaced.insert(replaced.clone());
+      self.live_paths.remove(replaced);
+      self.hashes.retain(|_, path| path != replaced);
+    }
+    if !self.replaced.contains(&record.path) {
+      if record.duplicate.is_none() {
+        self.live_paths.insert(record.path.clone());
+      }
+      if let Some(ref sha256) = record.sha256 {
+        self.hashes.entry(sha256.clone()).or_insert(record.path.clone());
+      }
+    }
+    self.records.push(record);
+  }
+
+  /// True if this entry, or an entry with the same fingerprint, has already been stored.
+  pub fn contains_entry(&self, entry: &EntryDetail) -> bool {
+    self.entry_ids.contains(&entry.id) ||
+    entry.fingerprint.as_ref().map_or(false, |f| self.fingerprints.contains(f))
+  }
+
+  pub fn contains_url(&self, url: &str) -> bool {
+    self.urls.contains(url)
+  }
+
+  /// Where an image with these contents was stored, if one was.
+  pub fn path_for_sha256(&self, sha256: &str) -> Option<&String> {
+    self.hashes.get(sha256)
+  }
+
+  /// True if an image was written to `path` and has not been replaced by a larger copy.
+  pub fn is_live(&self, path: &str) -> bool {
+    self.live_paths.contains(path)
+  }
+
+  pub fn records(&self) -> &[LedgerRecord] {
+    &self.records
+  }
+
+  /// Appends `record` to the ledger file.
+  pub fn record(&mut self, record: LedgerRecord) -> Result<()> {
+    if let Some(ref ledger_path) = self.path {
+      let mut line = try!(serde_json::to_vec(&record));
+      line.push(b'\n');
+      let mut file = try!(OpenOptions::new().create(true).append(true).open(ledger_path));
+      try!(file.write_all(&line));
+    }
+    self.remember(record);
+    Ok(())
+  }
+}
+
+/// A record of `url`, from `entry`, being stored at `path` now. The optional fields are
+/// left for the caller to fill in.
+pub fn new_record(entry: &EntryDetail, url: &str, path: &str) -> LedgerRecord {
+  LedgerRecord {
+    entry_id: entry.id.clone(),
+    fingerprint: entry.fingerprint.clone(),
+    url: url.to_string(),
+    path: path.to_string(),
+    stored: now(),
+    sha256: None,
+    duplicate: None,
+    dhash: None,
+    pixels: None,
+    replaces: None,
+  }
+}
+
+fn now() -> u64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+  use generated::EntryDetail;
+  use std::env;
+  use std::fs;
+  use super::*;
+
+  fn entry(id: &str, fingerprint: Option<&str>) -> EntryDetail {
+    EntryDetail {
+      id: id.to_string(),
+      fingerprint: fingerprint.map(|f| f.to_string()),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn reads_records() {
+    let reader = "{\"entryId\":\"id1\",\"fingerprint\":\"fp1\",\"url\":\"http://a/1.jpg\",\
+                  \"path\":\"1.jpg\",\"stored\":5}\n\n\
+                  {\"entryId\":\"id2\",\"fingerprint\":null,\"url\":\"http://a/2.jpg\",\
+                  \"path\":\"2.jpg\",\"stored\":6}\n"
+      .as_bytes();
+    let ledger = Ledger::new_with_bufread(reader).unwrap();
+    assert_eq!(2, ledger.records().len());
+    assert!(ledger.contains_entry(&entry("id1", None)));
+    assert!(ledger.contains_entry(&entry("id3", Some("fp1"))));
+    assert!(!ledger.contains_entry(&entry("id3", Some("fp3"))));
+    assert!(ledger.contains_url("http://a/2.jpg"));
+    assert!(!ledger.contains_url("http://a/3.jpg"));
+  }
+
+  #[test]
+  fn bad_record() {
+    Ledger::new_with_bufread("not json\n".as_bytes()).unwrap_err();
+  }
+
+  #[test]
+  fn record_appends_to_file() {
+    let path = env::temp_dir().join("fdown_ledger_test");
+    let _ = fs::remove_file(&path);
+
+    let mut ledger = Ledger::open(&path).unwrap();
+    assert_eq!(0, ledger.records().len());
+    let mut first = new_record(&entry("id1", Some("fp1")), "http://a/1.jpg", "1.jpg");
+    first.sha256 = Some("abc".to_string());
+    first.dhash = Some(12);
+    ledger.record(first).unwrap();
+    let mut second = new_record(&entry("id2", None), "http://a/2.jpg", "1.jpg");
+    second.sha256 = Some("abc".to_string());
+    second.duplicate = Some(true);
+    ledger.record(second).unwrap();
+    assert!(ledger.contains_entry(&entry("id1", None)));
+
+    let reopened = Ledger::open(&path).unwrap();
+    assert_eq!(2, reopened.records().len());
+    assert_eq!("id2", reopened.records()[1].entry_id);
+    assert_eq!("1.jpg", reopened.records()[1].path);
+    assert_eq!(Some(true), reopened.records()[1].duplicate);
+    assert_eq!(None, reopened.records()[0].duplicate);
+    assert_eq!(Some(12), reopened.records()[0].dhash);
+    assert!(reopened.contains_url("http://a/1.jpg"));
+    assert_eq!("1.jpg", reopened.path_for_sha256("abc").unwrap());
+    assert_eq!(None, reopened.path_for_sha256("def"));
+  }
+
+  #[test]
+  fn replaced_records() {
+    let path = env::temp_dir().join("fdown_ledger_replaced_test");
+    let _ = fs::remove_file(&path);
+
+    let mut ledger = Ledger::open(&path).unwrap();
+    let mut small = new_record(&entry("id1", None), "http://a/s.jpg", "s.jpg");
+    small.sha256 = Some("small".to_string());
+    ledger.record(small).unwrap();
+    assert!(ledger.is_live("s.jpg"));
+    let mut large = new_record(&entry("id2", None), "http://a/l.jpg", "l.jpg");
+    large.sha256 = Some("large".to_string());
+    large.replaces = Some("s.jpg".to_string());
+    ledger.record(large).unwrap();
+
+    let reopened = Ledger::open(&path).unwrap();
+    for ledger in &[ledger, reopened] {
+      assert!(!ledger.is_live("s.jpg"));
+      assert!(ledger.is_live("l.jpg"));
+      assert_eq!(None, ledger.path_for_sha256("small"));
+      assert_eq!("l.jpg", ledger.path_for_sha256("large").unwrap());
+      // Still skipped, so the smaller copy is not fetched again.
+      assert!(ledger.contains_url("http://a/s.jpg"));
+    }
+  }
+
+  #[test]
+  fn replaced_before_recorded() {
+    // Entry A replaced entry B's image, but B comes later in the stream, so its record was
+    // appended after A's.
+    let reader = "{\"entryId\":\"a\",\"fingerprint\":null,\"url\":\"http://a/l.jpg\",\
+                  \"path\":\"l.jpg\",\"stored\":5,\"sha256\":\"large\",\
+                  \"replaces\":\"s.jpg\"}\n\
+                  {\"entryId\":\"b\",\"fingerprint\":null,\"url\":\"http://a/s.jpg\",\
+                  \"path\":\"s.jpg\",\"stored\":5,\"sha256\":\"small\"}\n"
+      .as_bytes();
+    let ledger = Ledger::new_with_bufread(reader).unwrap();
+    assert!(!ledger.is_live("s.jpg"));
+    assert!(ledger.is_live("l.jpg"));
+    assert_eq!(None, ledger.path_for_sha256("small"));
+    assert_eq!("l.jpg", ledger.path_for_sha256("large").unwrap());
+    assert!(ledger.contains_url("http://a/s.jpg"));
+  }
+}
@@ -0,0 +1,203 @@
+use config;
+use feedly;
+use generated::{FeedlyTokenRequest, OAuthTokenResponse};
+use http::check_status;
+use hyper::{Client, Url};
+use hyper::header::ContentType;
+use rand::{self, Rng};
+use result::{FdownError, Result};
+use serde_json;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+
+pub const DEFAULT_REDIRECT_URI: &'static str = "http://localhost:8080/";
+const AUTH_PATH: &'static str = "/v3/auth/auth";
+const SCOPE: &'static str = "https://cloud.feedly.com/subscriptions";
+
+/// The Feedly app that fdown logs in as. The redirect URI must be registered with the app.
+pub struct FeedlyApp<'a> {
+  pub client_id: &'a str,
+  pub client_secret: &'a str,
+  pub redirect_uri: &'a str,
+  /// Feedly's server, e.g. `feedly::DEFAULT_BASE_URL` or a sandbox.
+  pub base_url: &'a str,
+}
+
+/// Runs Feedly's authorization code flow: the user approves fdown in a browser, Feedly
+/// redirects back to a listener on this machine, and the code it carries is exchanged for
+/// tokens, which are written to the config file.
+pub fn login(app: &FeedlyApp, config_file: &str, client: &Client) -> Result<()> {
+  let redirect = try!(Url::parse(app.redirect_uri)
+    .map_err(|_| FdownError::BadConfig(format!("bad redirect uri: {}", app.redirect_uri))));
+  let host = redirect.host_str().unwrap_or("localhost");
+  let port = redirect.port_or_known_default().unwrap_or(80);
+  let listener = try!(TcpListener::bind((host, port)));
+
+  let state = format!("{:016x}", rand::thread_rng().gen::<u64>());
+  let auth_url = try!(Url::parse_with_params(&format!("{}{}", app.base_url, AUTH_PATH),
+                                             &[("response_type", "code"),
+                                               ("client_id", app.client_id),
+                                               ("redirect_uri", app.redirect_uri),
+                                               ("scope", SCOPE),
+                                               ("state", state.as_str())])
+    .map_err(|_| FdownError::BadConfig(format!("bad feedlyBaseUrl: {}", app.base_url))));
+  println!("Open this URL in a browser to log in to Feedly:\n\n  {}\n", auth_url);
+
+  let code = try!(wait_for_code(&listener, &state));
+  let tokens = try!(exchange_code(app, &code, client));
+  let userid = try!(tokens.id
+    .ok_or(FdownError::Login("Feedly did not say which user logged in".to_string())));
+  let refresh_token = try!(tokens.refresh_token
+    .ok_or(FdownError::Login("Feedly did not issue a refresh token".to_string())));
+  try!(config::update_file(config_file,
+                           &[("userid", userid.as_str()),
+                             ("token", tokens.access_token.as_str()),
+                             ("refreshToken", refresh_token.as_str())]));
+  println!("Logged in as {}. Saved the tokens in {}.", userid, config_file);
+  Ok(())
+}
+
+/// Answers requests to the redirect URI until one of them carries a code or an error.
+fn wait_for_code(listener: &TcpListener, state: &str) -> Result<String> {
+  for stream in listener.incoming() {
+    let mut stream = try!(stream);
+    let request_line = try!(read_request_line(&stream));
+    match parse_redirect(&request_line, state) {
+      None => {
+        // Browsers also ask for things like favicon.ico.
+        try!(respond(&mut stream, "404 Not Found", "Not found."));
+      }
+      Some(Ok(code)) => {
+        try!(respond(&mut stream, "200 OK", "fdown is logged in. You can close this window."));
+        return Ok(code);
+      }
+      Some(Err(e)) => {
+        try!(respond(&mut stream, "400 Bad Request", "fdown could not log in."));
+        return Err(e);
+      }
+    }
+  }
+  Err(FdownError::Login("stopped listening for the redirect".to_string()))
+}
+
+fn read_request_line(stream: &TcpStream) -> Result<String> {
+  let mut reader = BufReader::new(stream);
+  let mut request_line = String::new();
+  try!(reader.read_line(&mut request_line));
+  // Read the headers too, so that the browser is not cut off mid-request.
+  let mut line = String::new();
+  while try!(reader.read_line(&mut line)) > 0 && line.trim() != "" {
+    line.clear();
+  }
+  Ok(request_line)
+}
+
+fn respond(stream: &mut TcpStream, status: &str, message: &str) -> Result<()> {
+  try!(write!(stream,
+              "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: \
+               close\r\n\r\n{}",
+              status,
+              message.len(),
+              message));
+  Ok(())
+}
+
+/// Pulls the authorization code out of a request line like `GET /?code=...&state=...
+/// HTTP/1.1`. None if the request has neither a code nor an error, or has an error without
+/// our state, which anything on the machine could send to abort the login.
+fn parse_redirect(request_line: &str, state: &str) -> Option<Result<String>> {
+  let target = match request_line.split_whitespace().nth(1) {
+    Some(target) => target,
+    None => return None,
+  };
+  let url = match Url::parse(&format!("http://localhost{}", target)) {
+    Ok(url) => url,
+    Err(_) => return None,
+  };
+  let mut code = None;
+  let mut error = None;
+  let mut returned_state = None;
+  for (name, value) in url.query_pairs() {
+    match &*name {
+      "code" => code = Some(value.into_owned()),
+      "error" => error = Some(value.into_owned()),
+      "state" => returned_state = Some(value.into_owned()),
+      _ => {}
+    }
+  }
+  let state_matches = returned_state.as_ref().map(|s| s.as_str()) == Some(state);
+  if let Some(error) = error {
+    if state_matches {
+      return Some(Err(FdownError::Login(format!("Feedly said: {}", error))));
+    }
+    return None;
+  }
+  code.map(|code| {
+    if state_matches {
+      Ok(code)
+    } else {
+      Err(FdownError::Login("the redirect's state did not match".to_string()))
+    }
+  })
+}
+
+fn exchange_code(app: &FeedlyApp, code: &str, client: &Client) -> Result<OAuthTokenResponse> {
+  let body = try!(serde_json::to_vec(&FeedlyTokenRequest {
+    grant_type: "authorization_code",
+    client_id: app.client_id,
+    client_secret: app.client_secret,
+    refresh_token: None,
+    code: Some(code),
+    redirect_uri: Some(app.redirect_uri),
+  }));
+  let url = format!("{}{}", app.base_url, feedly::TOKEN_PATH);
+  let response = try!(client.post(&url)
+    .body(body.as_slice())
+    .header(ContentType::json())
+    .send());
+  let response = try!(check_status(&url, response));
+  Ok(try!(serde_json::from_reader(response)))
+}
+
+#[cfg(test)]
+mod tests {
+  use result::FdownError;
+  use super::parse_redirect;
+
+  #[test]
+  fn code() {
+    assert_eq!("abc/def",
+               parse_redirect("GET /?code=abc%2Fdef&state=s1 HTTP/1.1\r\n", "s1")
+                 .unwrap()
+                 .unwrap());
+  }
+
+  #[test]
+  fn wrong_state() {
+    match parse_redirect("GET /?code=abc&state=other HTTP/1.1", "s1") {
+      Some(Err(FdownError::Login(_))) => {}
+      _ => panic!("expected a Login error"),
+    }
+    parse_redirect("GET /?code=abc HTTP/1.1", "s1").unwrap().unwrap_err();
+  }
+
+  #[test]
+  fn error() {
+    match parse_redirect("GET /?error=access_denied&state=s1 HTTP/1.1", "s1") {
+      Some(Err(FdownError::Login(message))) => assert_eq!("Feedly said: access_denied", message),
+      _ => panic!("expected a Login error"),
+    }
+  }
+
+  #[test]
+  fn error_with_wrong_state() {
+    assert!(parse_redirect("GET /?error=access_denied&state=other HTTP/1.1", "s1").is_none());
+    assert!(parse_redirect("GET /?error=access_denied HTTP/1.1", "s1").is_none());
+  }
+
+  #[test]
+  fn not_a_redirect() {
+    assert!(parse_redirect("GET /favicon.ico HTTP/1.1", "s1").is_none());
+    assert!(parse_redirect("", "s1").is_none());
+  }
+}
@@ -1,121 +1,245 @@
+extern crate chrono;
 extern crate clap;
+extern crate crossbeam;
+extern crate crypto;
 #[macro_use]
 extern crate hyper;
+extern crate image;
 #[macro_use]
 extern crate lazy_static;
+extern crate rand;
 extern crate regex;
+extern crate serde;
 extern crate serde_json;
+extern crate tempfile;
+#[macro_use]
+extern crate url;
 
+mod archive;
 mod args;
 mod config;
+mod download;
+mod dump;
 mod dropbox;
 mod feedly;
+mod filetype;
 mod generated;
+mod hash;
+mod http;
+mod ledger;
+mod login;
+mod perceptual;
+mod pool;
 mod result;
+mod retry;
+mod rewrite;
+mod select;
+mod sink;
+mod template;
+mod timestamp;
 
+use archive::Archiver;
+use args::Command;
 use config::ConfigFile;
+use download::Downloader;
 use dropbox::Dropbox;
-use feedly::Feedly;
+use dump::DumpFormat;
+use feedly::{EntryAction, Feedly, Stream};
+use chrono::NaiveDateTime;
 use generated::EntryDetail;
-use hyper::{Client, Url};
+use http::RefreshToken;
+use hyper::Client;
+use ledger::Ledger;
 use result::{FdownError, Result};
-use std::ffi::OsStr;
-use std::fs::File;
-use std::io::Read;
-use std::io::Write;
-use std::path::{Path, PathBuf};
+use retry::RetryPolicy;
+use rewrite::Rewriter;
+use select::FeedSelector;
+use serde_json::Value;
+use sink::{DirectorySink, DropboxSink, Sink};
+use std::collections::{HashMap, HashSet};
+use std::process;
+use std::sync::Arc;
+use template::PathTemplate;
 
-// TODO: allow selecting feeds, not just categories.
 // TODO: use hyper.url wherever you can
-// TODO: determine file type of files without extension.
-// TODO: add flag to conf dest dir for Dropbox
-// TODO: add flag to allow local writes (no Dropbox)
-// TODO: allow listing feeds
-// TODO: allow specifying feeds/categories by id.
-// TODO: add option to dump JSON to somewhere
 // TODO: add logging
 // TODO: allow putting cmd line args in config file
 
-fn download_image(url: &String) -> Result<(Vec<u8>)> {
-  let client = Client::new();
-  let mut response = try!(client.get(url).send());
-  let mut buf: Vec<u8> = Vec::new();
-  try!(response.read_to_end(&mut buf));
-  Ok(buf)
+/// What to do in Feedly to the entries whose images were stored, in order.
+fn entry_actions(args: &args::Args) -> Vec<EntryAction> {
+  let mut actions: Vec<EntryAction> =
+    args.tags().iter().map(|label| EntryAction::Tag(label.to_string())).collect();
+  actions.extend(args.untags().iter().map(|label| EntryAction::Untag(label.to_string())));
+  if args.should_mark_read() {
+    actions.push(EntryAction::MarkAsRead);
+  }
+  if args.should_unsave() {
+    actions.push(EntryAction::Unsave);
+  }
+  actions
 }
 
-fn add_number_suffix(stem: &OsStr, num: usize) -> Result<String> {
-  if let Some(stem) = stem.to_str() {
-    return Ok(format!("{}_({})", stem, num));
+fn make_sink(args: &args::Args,
+             config: &ConfigFile,
+             client: Arc<Client>,
+             retry: RetryPolicy)
+    -> Result<Box<Sink>> {
+  if let Some(dir) = output_dir(args, config) {
+    return Ok(Box::new(DirectorySink::new(dir)));
+  }
+  let folder = config.optional_string("dropboxFolder")
+    .map(|s| s.as_str())
+    .unwrap_or(sink::DEFAULT_DROPBOX_FOLDER);
+  let dropbox_token = config.optional_string("dropboxToken").map(|s| s.as_str());
+  let mut dropbox = Dropbox::new(dropbox_token, client);
+  dropbox.set_retry_policy(retry);
+  dropbox.set_base_urls(config.optional_string("dropboxContentUrl")
+                          .map(|s| s.as_str())
+                          .unwrap_or(dropbox::DEFAULT_CONTENT_URL),
+                        config.optional_string("dropboxApiUrl")
+                          .map(|s| s.as_str())
+                          .unwrap_or(dropbox::DEFAULT_API_URL));
+  if let Some(refresh_token) = config.optional_string("dropboxRefreshToken") {
+    dropbox.set_refresh_token(RefreshToken {
+      refresh_token: refresh_token.clone(),
+      client_id: try!(config.required_string("dropboxAppKey")).clone(),
+      client_secret: try!(config.required_string("dropboxAppSecret")).clone(),
+    });
+    dropbox.save_tokens_to(args.config_file_location());
+  } else if dropbox_token.is_none() {
+    return Err(FdownError::BadConfig("Required config value, dropboxToken or \
+                                      dropboxRefreshToken, missing"
+      .to_string()));
   }
-  Err(result::FdownError::BadFormat(format!("cannot append number to stem: {}",
-                                            stem.to_string_lossy())))
+  Ok(Box::new(DropboxSink::new(dropbox, folder)))
 }
 
-fn file_new_path(path: PathBuf) -> Result<PathBuf> {
-  let mut num = 0;
-  let extension = path.extension().unwrap_or(OsStr::new(""));
-  if let Some(stem) = path.file_stem() {
-    loop {
-      num += 1;
-      let new_name = try!(add_number_suffix(stem, num));
-      let new_path = path.with_file_name(new_name).with_extension(extension);
-      if !new_path.exists() {
-        return Ok(new_path);
-      }
+/// Where images go when they are not stored in Dropbox.
+fn output_dir<'a>(args: &'a args::Args, config: &'a ConfigFile) -> Option<&'a str> {
+  args.output_dir().or(config.optional_string("outputDir").map(|s| s.as_str()))
+}
+
+fn feedly_base_url(config: &ConfigFile) -> &str {
+  config.optional_string("feedlyBaseUrl")
+    .map(|s| s.trim_right_matches('/'))
+    .unwrap_or(feedly::DEFAULT_BASE_URL)
+}
+
+/// The credentials for refreshing the Feedly access token, if `fdown login` saved any.
+fn feedly_refresh_token(config: &ConfigFile) -> Result<Option<RefreshToken>> {
+  match config.optional_string("refreshToken") {
+    Some(refresh_token) => {
+      Ok(Some(RefreshToken {
+        refresh_token: refresh_token.clone(),
+        client_id: try!(config.required_string("feedlyClientId")).clone(),
+        client_secret: try!(config.required_string("feedlyClientSecret")).clone(),
+      }))
     }
+    None => Ok(None),
   }
-  Err(FdownError::BadFormat(format!("unable to find file stem in url: {}",
-                                    path.to_string_lossy())))
 }
 
-fn filepath_for_url(url: &String) -> Result<PathBuf> {
-  // Get the likely filename from the url.
-  // TODO: check to ensure that the filename has an extension.
-  if let Some(slash_index) = url.rfind('/') {
-    let filename = Path::new(&url[slash_index + 1..]);
-    let path = Path::new("/Users/gmadrid/Dropbox/ATestDir").join(filename);
-    if path.exists() {
-      return file_new_path(path);
+fn login_to_feedly(args: &args::Args, config: &ConfigFile, client: &Client) -> Result<()> {
+  let app = login::FeedlyApp {
+    client_id: try!(config.required_string("feedlyClientId")),
+    client_secret: try!(config.required_string("feedlyClientSecret")),
+    redirect_uri: config.optional_string("feedlyRedirectUri")
+      .map(|s| s.as_str())
+      .unwrap_or(login::DEFAULT_REDIRECT_URI),
+    base_url: feedly_base_url(config),
+  };
+  login::login(&app, args.config_file_location(), client)
+}
+
+fn make_retry_policy(config: &ConfigFile) -> Result<RetryPolicy> {
+  let max_attempts = try!(config.optional_u64("maxAttempts"));
+  let base_delay_ms = try!(config.optional_u64("retryDelayMs"));
+  Ok(RetryPolicy::new(max_attempts.unwrap_or(retry::DEFAULT_MAX_ATTEMPTS),
+                      base_delay_ms.unwrap_or(retry::DEFAULT_BASE_DELAY_MS)))
+}
+
+fn make_rewriter(config: &ConfigFile) -> Result<Rewriter> {
+  let mut rewriter = Rewriter::new();
+  for rule in config.values_with_prefix(rewrite::CONFIG_PREFIX) {
+    try!(rewriter.add_rule(rule));
+  }
+  Ok(rewriter)
+}
+
+fn make_template(args: &args::Args, config: &ConfigFile) -> Result<PathTemplate> {
+  let template = args.path_template()
+    .or(config.optional_string("pathTemplate").map(|s| s.as_str()))
+    .unwrap_or(template::DEFAULT_TEMPLATE);
+  PathTemplate::new(template)
+}
+
+/// Maps each subscription's stream id to the label of its first category.
+fn category_labels(feedly: &Feedly) -> Result<HashMap<String, String>> {
+  let subs = try!(feedly.subscriptions());
+  let mut labels = HashMap::new();
+  for sub in subs {
+    if let Some(label) = sub.categories.first().and_then(|cat| cat.label.clone()) {
+      labels.insert(sub.id, label);
     }
-    return Ok(path);
   }
-  Err(result::FdownError::BadFormat(format!("unable to extract filename from url: {}", url)))
+  Ok(labels)
 }
 
-fn unsave_entries(entries: &Vec<&EntryDetail>, feedly: &Feedly) -> Result<()> {
-  feedly.unsave_entries(entries)
+fn open_ledger(args: &args::Args, config: &ConfigFile) -> Result<Ledger> {
+  let path = args.ledger_file()
+    .or(config.optional_string("ledgerFile").map(|s| s.as_str()))
+    .unwrap_or(ledger::DEFAULT_LEDGER);
+  Ledger::open(config::expand_path(path))
 }
 
-fn upload_entry(entry: &EntryDetail, dropbox: &Dropbox) -> Result<()> {
-  if let Some(url) = Feedly::extract_image_url(entry) {
-    let url = Feedly::tumblr_filter(url);
-    let image_bytes = try!(download_image(&url));
-    let url_s = Url::parse(url.as_str()).unwrap();
-    let path = url_s.path();
-    let slash = path.rfind('/').unwrap();
-    let filename = &path[slash + 1..];
-    let f = "/Media/Porn/Inbox/".to_string() + filename;
-    try!(dropbox.upload(f.as_str(), image_bytes.as_slice()));
-    return Ok(());
+fn list_history(ledger: &Ledger) -> Result<()> {
+  for record in ledger.records() {
+    let when = NaiveDateTime::from_timestamp(record.stored as i64, 0);
+    println!("{}: {} -> {} ({})", when, record.entry_id, record.path, record.url);
+  }
+  Ok(())
+}
+
+fn list_categories(feedly: &Feedly) -> Result<()> {
+  for category in try!(feedly.categories()) {
+    println!("{}", category.label.unwrap_or(category.id));
   }
-  Err(result::FdownError::MissingUrl(entry.id.clone()))
+  Ok(())
 }
 
-fn write_entry(entry: &EntryDetail) -> Result<()> {
-  if let Some(url) = Feedly::extract_image_url(entry) {
-    let url = Feedly::tumblr_filter(url);
-    let image_bytes = try!(download_image(&url));
-    let path = try!(filepath_for_url(&url));
-    let mut file = try!(File::create(path));
-    try!(file.write(&image_bytes));
-    return Ok(());
+fn dump_format(args: &args::Args, path: &str) -> Result<DumpFormat> {
+  match args.dump_format() {
+    Some(name) => DumpFormat::from_name(name),
+    None => Ok(DumpFormat::for_path(path)),
+  }
+}
+
+/// Writes the entries to the `--dump-entries` file, if one was given.
+fn dump_entries(args: &args::Args, feedly: &Feedly, entries: &[EntryDetail]) -> Result<()> {
+  if let Some(path) = args.dump_entries() {
+    let format = try!(dump_format(args, path));
+    if args.dump_raw() {
+      let raw: Vec<Value> = entries.iter().filter_map(|e| feedly.raw_entry(&e.id)).collect();
+      try!(dump::write(path, format, &raw));
+    } else {
+      try!(dump::write(path, format, entries));
+    }
+    println!("Dumped {} entries to {}.", entries.len(), path);
   }
-  Err(result::FdownError::MissingUrl(entry.id.clone()))
+  Ok(())
 }
 
-fn list_subs(feedly: &Feedly) -> Result<()> {
+fn list_subs(args: &args::Args, feedly: &Feedly) -> Result<()> {
   let subs = try!(feedly.subscriptions());
+  if let Some(path) = args.dump_subs() {
+    let format = try!(dump_format(args, path));
+    if args.dump_raw() {
+      try!(dump::write(path, format, &feedly.raw_subscriptions()));
+    } else {
+      try!(dump::write(path, format, &subs));
+    }
+    println!("Dumped {} subscriptions to {}.", subs.len(), path);
+  }
   for sub in subs {
     // TODO: print something better.
     let title = sub.title.unwrap_or(sub.id);
@@ -131,87 +255,270 @@ fn list_subs(feedly: &Feedly) -> Result<()> {
   Ok(())
 }
 
-fn filter_for_category(category: Option<&str>,
-                       feedly: &Feedly)
-    -> Result<Box<Fn(&EntryDetail) -> bool>> {
-  if let Some(category) = category {
-    let subs = try!(feedly.subscriptions());
-    let stream_ids: Vec<String> = subs.iter()
-      .filter(|sub| {
-        let categories = &sub.categories;
-        categories.iter().any(|cat| cat.label.as_ref().map_or(false, |label| label == category))
-      })
-      .map(|sub| sub.id.to_string())
-      .collect();
-
-    let closure = move |entry: &EntryDetail| -> bool {
-      if let Some(ref origin) = entry.origin {
-        return stream_ids.iter().any(|stream_id| stream_id == &origin.stream_id);
-      }
-      false
-    };
-    return Ok(Box::new(closure));
+fn feed_selector(args: &args::Args) -> Result<FeedSelector> {
+  let mut selector = FeedSelector::new();
+  if let Some(category) = args.filter_category() {
+    selector.include_category(category);
+  }
+  for pattern in args.feeds() {
+    try!(selector.include_feed(pattern));
+  }
+  for id in args.feed_ids() {
+    selector.include_feed_id(id);
+  }
+  for pattern in args.excluded_feeds() {
+    try!(selector.exclude_feed(pattern));
+  }
+  for category in args.excluded_categories() {
+    selector.exclude_category(category);
   }
-  Ok(Box::new(|_| true))
+  Ok(selector)
 }
 
-fn get_entries(filter_func: &Fn(&EntryDetail) -> bool,
+fn filter_for_feeds(selector: &FeedSelector,
+                    feedly: &Feedly)
+    -> Result<Box<Fn(&EntryDetail) -> bool>> {
+  let subs = if selector.needs_subscriptions() {
+    try!(feedly.subscriptions())
+  } else {
+    Vec::new()
+  };
+  let filter = selector.filter(&subs);
+  Ok(Box::new(move |entry: &EntryDetail| filter.matches(entry)))
+}
+
+/// The entries with images to store. Entries whose images are all in the ledger are left
+/// out unless `keep_stored`, so that a later run can still unsave or tag them. Entries with
+/// an image that failed are kept, so that it is retried.
+fn get_entries(stream: &Stream,
+               filter_func: &Fn(&EntryDetail) -> bool,
                count: usize,
-               feedly: &Feedly)
+               feedly: &Feedly,
+               archiver: &Archiver,
+               keep_stored: bool)
     -> Result<Vec<EntryDetail>> {
-  // TODO: give this a count param
-  // TODO: keep continuing until you have count entries
-  let ids = try!(feedly.saved_entry_ids(count));
-  let entries = try!(feedly.detail_for_entries(ids));
-  let res: Vec<EntryDetail> = entries.into_iter()
-    .filter(filter_func)
-    .filter(|e| {
-      if let Some(ref v) = e.visual {
-        if let Some(ref u) = v.url {
-          return u.starts_with("http");
-        }
-      }
-      false
-    })
-    .collect();
-  Ok(res)
+  feedly.stream_entries(stream, count, &|e: &EntryDetail| {
+    filter_func(e) && !Feedly::extract_image_urls(e).is_empty() &&
+    (keep_stored || !archiver.is_stored(e))
+  })
+}
+
+/// Lists the entries in the stream that pass the filter, without storing anything.
+fn list_entries(args: &args::Args, feedly: &Feedly) -> Result<()> {
+  let stream = feedly.stream(args.stream(), args.unread_only());
+  let filter = try!(filter_for_feeds(&try!(feed_selector(args)), feedly));
+  let entries = try!(feedly.stream_entries(&stream, args.number_of_entries(), filter.as_ref()));
+  try!(dump_entries(args, feedly, &entries));
+  for entry in entries {
+    let feed = entry.origin.as_ref().and_then(|o| o.title.as_ref()).map_or("", |t| t.as_str());
+    let title = entry.title.as_ref().map_or("", |t| t.lines().next().unwrap_or(""));
+    let published = entry.published.map_or(String::new(), |t| t.to_naive().date().to_string());
+    println!("{}: {} [{}, {}] ({} images)",
+             entry.id,
+             title,
+             feed,
+             published,
+             Feedly::extract_image_urls(&entry).len());
+  }
+  Ok(())
+}
+
+/// Unsaves the entries named on the command line, and the saved entries in the selected
+/// feeds.
+fn unsave(args: &args::Args, feedly: &Feedly) -> Result<()> {
+  let mut ids: Vec<String> = args.entry_ids().iter().map(|id| id.to_string()).collect();
+  let selector = try!(feed_selector(args));
+  if selector.has_inclusions() {
+    let filter = try!(filter_for_feeds(&selector, feedly));
+    let entries = try!(feedly.stream_entries(&feedly.saved_stream(),
+                                             args.number_of_entries(),
+                                             filter.as_ref()));
+    ids.extend(entries.into_iter().map(|entry| entry.id));
+  }
+  let count = ids.len();
+  if count > 0 {
+    try!(feedly.apply(&EntryAction::Unsave, &ids));
+  }
+  println!("Unsaved {} entries.", count);
+  Ok(())
+}
+
+fn show_status(args: &args::Args, config: &ConfigFile) -> Result<()> {
+  match config.optional_string("userid") {
+    Some(userid) => println!("Feedly user: {}", userid),
+    None => println!("Feedly user: not logged in"),
+  }
+  if config.optional_string("refreshToken").is_some() {
+    println!("Feedly token: refreshed automatically");
+  }
+  match output_dir(args, config) {
+    Some(dir) => println!("Destination: {}", dir),
+    None => {
+      println!("Destination: Dropbox {}",
+               config.optional_string("dropboxFolder")
+                 .map(|s| s.as_str())
+                 .unwrap_or(sink::DEFAULT_DROPBOX_FOLDER))
+    }
+  }
+  let ledger = try!(open_ledger(args, config));
+  let entries: HashSet<&str> = ledger.records().iter().map(|r| r.entry_id.as_str()).collect();
+  println!("Stored {} images from {} entries.", ledger.records().len(), entries.len());
+  if args.list_history() {
+    try!(list_history(&ledger));
+  }
+  Ok(())
+}
+
+fn make_feedly(args: &args::Args,
+               config: &ConfigFile,
+               client: Arc<Client>,
+               retry: RetryPolicy)
+    -> Result<Feedly> {
+  let userid = try!(config.required_string("userid"));
+  let token = try!(config.required_string("token"));
+  let mut feedly = Feedly::new(userid, token, client);
+  feedly.set_retry_policy(retry);
+  feedly.set_base_url(feedly_base_url(config));
+  if let Some(refresh) = try!(feedly_refresh_token(config)) {
+    feedly.set_refresh_token(refresh);
+    feedly.save_tokens_to(args.config_file_location());
+  }
+  if args.dump_raw() {
+    feedly.keep_raw_responses();
+  }
+  Ok(feedly)
 }
 
 fn real_main() -> Result<()> {
   let args = try!(args::Args::parse());
   let config = try!(ConfigFile::new(args.config_file_location()));
 
-  let userid = try!(config.required_string("userid"));
-  let token = try!(config.required_string("token"));
-  let dropbox_token = try!(config.required_string("dropboxToken"));
+  // One client for everything, so that connections are kept alive and reused.
+  let client = Arc::new(Client::new());
+  let retry = try!(make_retry_policy(&config));
+  match args.command() {
+    Command::Login => login_to_feedly(&args, &config, &client),
+    Command::Status => show_status(&args, &config),
+    Command::Subs => list_subs(&args, &try!(make_feedly(&args, &config, client, retry))),
+    Command::Categories => list_categories(&try!(make_feedly(&args, &config, client, retry))),
+    Command::Entries => list_entries(&args, &try!(make_feedly(&args, &config, client, retry))),
+    Command::Unsave => unsave(&args, &try!(make_feedly(&args, &config, client, retry))),
+    Command::Fetch => fetch(&args, &config, client, retry),
+  }
+}
 
-  let feedly = Feedly::new(userid, token);
-  let dropbox = Dropbox::new(&dropbox_token);
+fn fetch(args: &args::Args,
+         config: &ConfigFile,
+         client: Arc<Client>,
+         retry: RetryPolicy)
+    -> Result<()> {
+  let feedly = try!(make_feedly(args, config, client.clone(), retry));
+  let ledger = try!(open_ledger(args, config));
+  let sink = try!(make_sink(args, config, client.clone(), retry));
+  let downloader = Downloader::new(client.clone(), retry);
+  let rewriter = try!(make_rewriter(config));
+  let template = try!(make_template(args, config));
+  let categories = if template.uses_category() {
+    try!(category_labels(&feedly))
+  } else {
+    HashMap::new()
+  };
 
-  if args.list_subs() {
-    return list_subs(&feedly);
+  let mut archiver = Archiver::new(downloader, rewriter, sink, template, categories, ledger);
+  if let Some(distance) = try!(config.optional_u64("nearDuplicateDistance")) {
+    archiver.set_near_duplicate_distance(distance as u32);
   }
 
-  let filter = try!(filter_for_category(args.filter_category(), &feedly));
-  let entries = try!(get_entries(filter.as_ref(), args.number_of_entries(), &feedly));
+  let filter = try!(filter_for_feeds(&try!(feed_selector(args)), &feedly));
+  let stream = feedly.stream(args.stream(), args.unread_only());
+  let actions = entry_actions(args);
+  // Already stored entries count as stored, and the actions still apply to them.
+  let entries = try!(get_entries(&stream,
+                                 filter.as_ref(),
+                                 args.number_of_entries(),
+                                 &feedly,
+                                 &archiver,
+                                 !actions.is_empty()));
+  try!(dump_entries(args, &feedly, &entries));
   let mut successful_entries: Vec<&EntryDetail> = Vec::with_capacity(entries.len());
-  for (i, entry) in entries.iter().enumerate() {
+  let mut failures: Vec<(&EntryDetail, FdownError)> = Vec::new();
+  let mut skipped = 0;
+  let mut skipped_images = 0;
+  let archive = |_: usize, entry: &EntryDetail| archiver.archive_entry(entry);
+  pool::for_each_ordered(&entries, args.jobs(), archive, |i, mut result| {
+    let entry = &entries[i];
     println!("Processing entry {}.", i);
-    match upload_entry(entry, &dropbox) {
-      Ok(_) => successful_entries.push(entry),
-      Err(e) => return Err(e),
+    for image in &result.stored {
+      if image.duplicate {
+        println!("  {} duplicates {}, skipped ({})", image.url, image.path, entry.id);
+      } else if let Some(ref smaller) = image.replaced {
+        println!("  {} <- {}, replacing smaller copy {} ({})",
+                 image.path,
+                 image.url,
+                 smaller,
+                 entry.id);
+      } else if image.fallback > 0 {
+        println!("  {} <- {} (fallback {})", image.path, image.url, image.fallback);
+      } else {
+        println!("  {} <- {}", image.path, image.url);
+      }
     }
-    if args.write_to_directory() {
-      try!(write_entry(entry));
+    if result.skipped > 0 {
+      println!("  {} images already stored by an earlier run ({})", result.skipped, entry.id);
+      skipped_images += result.skipped;
     }
+    if let Err(e) = archiver.record(entry, &result) {
+      result.error = result.error.or(Some(e));
+    }
+    match result.error {
+      None => {
+        if result.stored.iter().all(|image| image.duplicate) {
+          println!("Skipping entry {}, already stored.", i);
+          skipped += 1;
+        }
+        successful_entries.push(entry);
+      }
+      Some(e) => {
+        println!("Entry {} failed: {:?}", i, e);
+        failures.push((entry, e));
+      }
+    }
+  });
+  let ids: Vec<String> = successful_entries.iter().map(|entry| entry.id.clone()).collect();
+  let applied = apply_actions(&feedly, &actions, &ids);
+
+  // The images are stored and recorded either way, so summarize them before any error.
+  print_summary(successful_entries.len() - skipped, skipped, skipped_images, &failures);
+  try!(applied);
+  if !failures.is_empty() {
+    return Err(FdownError::EntriesFailed(failures.len()));
   }
-  if args.should_unsave() {
-    try!(unsave_entries(&successful_entries, &feedly));
-  }
+  Ok(())
+}
 
+fn apply_actions(feedly: &Feedly, actions: &[EntryAction], ids: &[String]) -> Result<()> {
+  if ids.is_empty() {
+    return Ok(());
+  }
+  for action in actions {
+    try!(feedly.apply(action, ids));
+  }
   Ok(())
 }
 
+fn print_summary(stored: usize,
+                 skipped: usize,
+                 skipped_images: usize,
+                 failures: &[(&EntryDetail, FdownError)]) {
+  println!("Stored {}, skipped {}, failed {}.", stored, skipped, failures.len());
+  if skipped_images > 0 {
+    println!("{} images were already stored by earlier runs.", skipped_images);
+  }
+  for &(entry, ref err) in failures {
+    println!("  {}: {:?}", entry.id, err);
+  }
+}
+
 // TODO: check out the unwraps().
 fn main() {
   match real_main() {
@@ -220,7 +527,12 @@ fn main() {
       match err {
         // Clap gets special attention. ('-h' for example is better handled by clap::Error::exit())
         result::FdownError::Clap(ce) => clap::Error::exit(&ce),
-        _ => println!("{:?}", err),
+        // The summary has already been printed.
+        result::FdownError::EntriesFailed(_) => process::exit(1),
+        _ => {
+          println!("{:?}", err);
+          process::exit(1);
+        }
       }
     }
   }
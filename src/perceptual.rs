@@ -0,0 +1,189 @@
+use image::{self, DynamicImage, FilterType, GenericImage};
+
+// dHash compares each pixel of a 9x8 grayscale thumbnail with its right-hand neighbour.
+const HASH_WIDTH: u32 = 9;
+const HASH_HEIGHT: u32 = 8;
+
+/// A difference hash and the size of the image it came from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fingerprint {
+  pub dhash: u64,
+  /// Width times height.
+  pub pixels: u64,
+}
+
+impl Fingerprint {
+  /// Decodes `bytes` and fingerprints the image. `None` for formats that cannot be decoded,
+  /// such as video.
+  pub fn of(bytes: &[u8]) -> Option<Fingerprint> {
+    image::load_from_memory(bytes).ok().map(|img| Fingerprint::of_image(&img))
+  }
+
+  fn of_image(img: &DynamicImage) -> Fingerprint {
+    let (width, height) = img.dimensions();
+    let thumb = img.resize_exact(HASH_WIDTH, HASH_HEIGHT, FilterType::Triangle).to_luma();
+    let mut dhash = 0u64;
+    for y in 0..HASH_HEIGHT {
+      for x in 0..HASH_WIDTH - 1 {
+        dhash <<= 1;
+        if thumb.get_pixel(x, y).data[0] > thumb.get_pixel(x + 1, y).data[0] {
+          dhash |= 1;
+        }
+      }
+    }
+    Fingerprint {
+      dhash: dhash,
+      pixels: width as u64 * height as u64,
+    }
+  }
+
+  pub fn distance(&self, other: &Fingerprint) -> u32 {
+    (self.dhash ^ other.dhash).count_ones()
+  }
+}
+
+/// What to do with an image that may be a resized copy of one already stored.
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+  /// Nothing like it has been stored.
+  New,
+  /// A copy at least as large is stored at this path.
+  DuplicateOf(String),
+  /// This is a larger copy of the image stored at this path.
+  Supersedes(String),
+}
+
+/// The fingerprints of the images stored so far, for spotting the same picture at
+/// different sizes.
+pub struct NearDuplicates {
+  threshold: u32,
+  seen: Vec<(Fingerprint, String)>,
+}
+
+impl NearDuplicates {
+  /// Images whose hashes differ in at most `threshold` bits are treated as the same.
+  pub fn new(threshold: u32) -> NearDuplicates {
+    NearDuplicates {
+      threshold: threshold,
+      seen: Vec::new(),
+    }
+  }
+
+  pub fn add(&mut self, fingerprint: Fingerprint, path: &str) {
+    self.seen.push((fingerprint, path.to_string()));
+  }
+
+  pub fn remove(&mut self, path: &str) {
+    self.seen.retain(|&(_, ref p)| p != path);
+  }
+
+  /// Moves the image added as `from` with this fingerprint to `to`, leaving any other image
+  /// at `from` alone.
+  pub fn rename(&mut self, fingerprint: Fingerprint, from: &str, to: &str) {
+    let renamed = self.seen.iter_mut().rev().find(|seen| seen.0 == fingerprint && seen.1 == from);
+    if let Some(seen) = renamed {
+      seen.1 = to.to_string();
+    }
+  }
+
+  /// Compares `fingerprint` with the largest matching image seen. Unless it is a
+  /// duplicate, it is added as `path`, and any copy it supersedes is forgotten.
+  pub fn check(&mut self, fingerprint: Fingerprint, path: &str) -> Verdict {
+    let verdict = {
+      let best = self.seen
+        .iter()
+        .filter(|&&(ref seen, _)| seen.distance(&fingerprint) <= self.threshold)
+        .max_by_key(|&&(ref seen, _)| seen.pixels);
+      match best {
+        None => Verdict::New,
+        Some(&(ref seen, ref p)) if seen.pixels >= fingerprint.pixels => {
+          Verdict::DuplicateOf(p.clone())
+        }
+        Some(&(_, ref p)) => Verdict::Supersedes(p.clone()),
+      }
+    };
+    if let Verdict::DuplicateOf(_) = verdict {
+      return verdict;
+    }
+    if let Verdict::Supersedes(ref old) = verdict {
+      self.remove(old);
+    }
+    self.add(fingerprint, path);
+    verdict
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use image::{DynamicImage, FilterType, ImageBuffer, Luma};
+  use super::*;
+
+  fn fingerprint(dhash: u64, pixels: u64) -> Fingerprint {
+    Fingerprint {
+      dhash: dhash,
+      pixels: pixels,
+    }
+  }
+
+  fn gradient(size: u32) -> DynamicImage {
+    DynamicImage::ImageLuma8(ImageBuffer::from_fn(size, size, |x, y| {
+      Luma([((x * 7 + y * 3) * 255 / (size * 10)) as u8])
+    }))
+  }
+
+  #[test]
+  fn resized_copies_are_close() {
+    let large = gradient(400);
+    let small = large.resize_exact(120, 120, FilterType::Triangle);
+    let a = Fingerprint::of_image(&large);
+    let b = Fingerprint::of_image(&small);
+    assert_eq!(160000, a.pixels);
+    assert_eq!(14400, b.pixels);
+    assert!(a.distance(&b) <= 4);
+  }
+
+  #[test]
+  fn different_images_are_far() {
+    let a = Fingerprint::of_image(&gradient(100));
+    let b = Fingerprint::of_image(&gradient(100).fliph());
+    assert!(a.distance(&b) > 20);
+  }
+
+  #[test]
+  fn undecodable() {
+    assert_eq!(None, Fingerprint::of(b"<html></html>"));
+  }
+
+  #[test]
+  fn keeps_largest() {
+    let mut seen = NearDuplicates::new(3);
+    assert_eq!(Verdict::New, seen.check(fingerprint(0b1111, 100), "a.jpg"));
+    assert_eq!(Verdict::DuplicateOf("a.jpg".to_string()),
+               seen.check(fingerprint(0b1110, 50), "b.jpg"));
+    assert_eq!(Verdict::Supersedes("a.jpg".to_string()),
+               seen.check(fingerprint(0b0111, 400), "c.jpg"));
+    assert_eq!(Verdict::DuplicateOf("c.jpg".to_string()),
+               seen.check(fingerprint(0b1111, 400), "d.jpg"));
+    assert_eq!(Verdict::New, seen.check(fingerprint(0xff00, 100), "e.jpg"));
+  }
+
+  #[test]
+  fn remove() {
+    let mut seen = NearDuplicates::new(0);
+    seen.add(fingerprint(1, 100), "a.jpg");
+    seen.remove("a.jpg");
+    assert_eq!(Verdict::New, seen.check(fingerprint(1, 10), "b.jpg"));
+  }
+
+  #[test]
+  fn rename() {
+    let mut seen = NearDuplicates::new(0);
+    seen.add(fingerprint(0xf0, 100), "a.jpg");
+    assert_eq!(Verdict::New, seen.check(fingerprint(0x0f, 100), "a.jpg"));
+    seen.rename(fingerprint(0x0f, 100), "a.jpg", "a_(1).jpg");
+    assert_eq!(Verdict::DuplicateOf("a.jpg".to_string()),
+               seen.check(fingerprint(0xf0, 10), "b.jpg"));
+    assert_eq!(Verdict::DuplicateOf("a_(1).jpg".to_string()),
+               seen.check(fingerprint(0x0f, 10), "c.jpg"));
+  }
+}
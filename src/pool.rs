@@ -0,0 +1,95 @@
+use crossbeam;
+use std::cmp;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+/// Runs `work` over `items` on up to `jobs` threads. Each result is handed to `done` on the
+/// calling thread, in the same order as `items`, as soon as it and all earlier results are
+/// ready.
+pub fn for_each_ordered<T, R, W, D>(items: &[T], jobs: usize, work: W, mut done: D)
+  where T: Sync,
+        R: Send,
+        W: Fn(usize, &T) -> R + Sync,
+        D: FnMut(usize, R) {
+  let next = AtomicUsize::new(0);
+  let workers = cmp::max(1, cmp::min(jobs, items.len()));
+  let (tx, rx) = mpsc::channel();
+
+  crossbeam::scope(|scope| {
+    for _ in 0..workers {
+      let tx = tx.clone();
+      let next = &next;
+      let work = &work;
+      scope.spawn(move || {
+        loop {
+          let i = next.fetch_add(1, Ordering::SeqCst);
+          if i >= items.len() {
+            break;
+          }
+          if tx.send((i, work(i, &items[i]))).is_err() {
+            break;
+          }
+        }
+      });
+    }
+    // Only the workers hold senders now, so the receiver ends when they do.
+    drop(tx);
+
+    let mut pending = BTreeMap::new();
+    let mut next_done = 0;
+    for (i, result) in rx.iter() {
+      pending.insert(i, result);
+      while let Some(result) = pending.remove(&next_done) {
+        done(next_done, result);
+        next_done += 1;
+      }
+    }
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use std::thread;
+  use std::time::Duration;
+  use super::*;
+
+  fn run(items: &[u64], jobs: usize) -> Vec<(usize, u64)> {
+    let mut results = Vec::new();
+    for_each_ordered(items,
+                     jobs,
+                     |_, item| {
+                       // Make earlier items finish later.
+                       thread::sleep(Duration::from_millis(20 - *item));
+                       item * 10
+                     },
+                     |i, r| results.push((i, r)));
+    results
+  }
+
+  #[test]
+  fn results_in_order() {
+    assert_eq!(vec![(0, 10), (1, 20), (2, 30), (3, 40), (4, 50)],
+               run(&[1, 2, 3, 4, 5], 3));
+  }
+
+  #[test]
+  fn single_job() {
+    assert_eq!(vec![(0, 10), (1, 20)], run(&[1, 2], 1));
+  }
+
+  #[test]
+  fn more_jobs_than_items() {
+    assert_eq!(vec![(0, 70)], run(&[7], 16));
+  }
+
+  #[test]
+  fn no_items() {
+    assert_eq!(Vec::<(usize, u64)>::new(), run(&[], 4));
+  }
+
+  #[test]
+  fn zero_jobs_still_runs() {
+    assert_eq!(vec![(0, 10)], run(&[1], 0));
+  }
+}
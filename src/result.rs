@@ -10,9 +10,19 @@ pub enum FdownError {
   BadConfig(String),
   BadFormat(String),
   Clap(clap::Error),
+  EntriesFailed(usize),
+  HttpStatus {
+    status: u16,
+    url: String,
+    message: Option<String>,
+    /// Seconds to wait before retrying, from the Retry-After header.
+    retry_after: Option<u64>,
+  },
   Hyper(hyper::Error),
   Io(io::Error),
+  Login(String),
   MissingUrl(String),
+  NotAnImage(String),
   ParseIntError(num::ParseIntError),
   SerdeJson(serde_json::Error),
 
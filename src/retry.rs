@@ -0,0 +1,178 @@
+use hyper;
+use rand::{self, Rng};
+use result::{FdownError, Result};
+use std::cmp;
+use std::thread;
+use std::time::Duration;
+
+pub const DEFAULT_MAX_ATTEMPTS: u64 = 4;
+pub const DEFAULT_BASE_DELAY_MS: u64 = 500;
+const MAX_DELAY_MS: u64 = 60 * 1000;
+
+/// How often, and how patiently, to retry requests that fail with network errors, 429s or 5xxs.
+#[derive(Clone, Copy)]
+pub struct RetryPolicy {
+  max_attempts: u64,
+  base_delay_ms: u64,
+  sleep: fn(Duration),
+}
+
+impl Default for RetryPolicy {
+  fn default() -> RetryPolicy {
+    RetryPolicy::new(DEFAULT_MAX_ATTEMPTS, DEFAULT_BASE_DELAY_MS)
+  }
+}
+
+impl RetryPolicy {
+  pub fn new(max_attempts: u64, base_delay_ms: u64) -> RetryPolicy {
+    RetryPolicy {
+      max_attempts: cmp::max(1, max_attempts),
+      base_delay_ms: base_delay_ms,
+      sleep: thread::sleep,
+    }
+  }
+
+  #[cfg(test)]
+  pub fn with_sleeper(max_attempts: u64, base_delay_ms: u64, sleep: fn(Duration)) -> RetryPolicy {
+    RetryPolicy { sleep: sleep, ..RetryPolicy::new(max_attempts, base_delay_ms) }
+  }
+
+  #[cfg(test)]
+  pub fn without_sleeping(max_attempts: u64) -> RetryPolicy {
+    fn no_sleep(_: Duration) {}
+    RetryPolicy::with_sleeper(max_attempts, 0, no_sleep)
+  }
+
+  /// Calls `f` until it succeeds, fails with an error that is not worth retrying, or has been
+  /// tried `max_attempts` times.
+  pub fn run<R, F>(&self, mut f: F) -> Result<R>
+    where F: FnMut() -> Result<R> {
+    let mut attempt = 1;
+    loop {
+      match f() {
+        Err(ref err) if attempt < self.max_attempts && is_retryable(err) => {
+          (self.sleep)(self.delay_for(attempt, err));
+          attempt += 1;
+        }
+        result => return result,
+      }
+    }
+  }
+
+  /// The server's Retry-After if it sent one, otherwise exponential backoff with jitter.
+  /// Either way, no more than `MAX_DELAY_MS`.
+  fn delay_for(&self, attempt: u64, err: &FdownError) -> Duration {
+    if let FdownError::HttpStatus { retry_after: Some(secs), .. } = *err {
+      return Duration::from_millis(cmp::min(MAX_DELAY_MS, secs.saturating_mul(1000)));
+    }
+    let backoff = self.backoff_ms(attempt);
+    let half = backoff / 2;
+    Duration::from_millis(half + rand::thread_rng().gen_range(0, half + 1))
+  }
+
+  fn backoff_ms(&self, attempt: u64) -> u64 {
+    let factor = 1u64.checked_shl(attempt.saturating_sub(1) as u32).unwrap_or(u64::max_value());
+    cmp::min(MAX_DELAY_MS, self.base_delay_ms.saturating_mul(factor))
+  }
+}
+
+fn is_retryable(err: &FdownError) -> bool {
+  match *err {
+    FdownError::Hyper(hyper::Error::Uri(_)) => false,
+    FdownError::Hyper(_) | FdownError::Io(_) => true,
+    FdownError::HttpStatus { status, .. } => status == 429 || status >= 500,
+    _ => false,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use result::FdownError;
+  use std::cell::Cell;
+  use std::time::Duration;
+  use super::*;
+
+  fn status_error(status: u16, retry_after: Option<u64>) -> FdownError {
+    FdownError::HttpStatus {
+      status: status,
+      url: "http://example.com/".to_string(),
+      message: None,
+      retry_after: retry_after,
+    }
+  }
+
+  #[test]
+  fn retries_until_success() {
+    let calls = Cell::new(0);
+    let result = RetryPolicy::without_sleeping(3).run(|| {
+      calls.set(calls.get() + 1);
+      if calls.get() < 3 {
+        Err(status_error(503, None))
+      } else {
+        Ok(calls.get())
+      }
+    });
+    assert_eq!(3, result.unwrap());
+  }
+
+  #[test]
+  fn gives_up_after_max_attempts() {
+    let calls = Cell::new(0);
+    let result: Result<()> = RetryPolicy::without_sleeping(3).run(|| {
+      calls.set(calls.get() + 1);
+      Err(status_error(429, None))
+    });
+    result.unwrap_err();
+    assert_eq!(3, calls.get());
+  }
+
+  #[test]
+  fn does_not_retry_client_errors() {
+    let calls = Cell::new(0);
+    let result: Result<()> = RetryPolicy::without_sleeping(3).run(|| {
+      calls.set(calls.get() + 1);
+      Err(status_error(404, None))
+    });
+    result.unwrap_err();
+    assert_eq!(1, calls.get());
+  }
+
+  #[test]
+  fn honors_retry_after() {
+    let policy = RetryPolicy::new(3, 500);
+    assert_eq!(Duration::from_secs(7), policy.delay_for(1, &status_error(429, Some(7))));
+  }
+
+  thread_local!(static SLEPT: Cell<Duration> = Cell::new(Duration::from_secs(0)));
+
+  fn record_sleep(duration: Duration) {
+    SLEPT.with(|slept| slept.set(slept.get() + duration));
+  }
+
+  #[test]
+  fn caps_retry_after() {
+    let calls = Cell::new(0);
+    let result: Result<()> = RetryPolicy::with_sleeper(2, 500, record_sleep).run(|| {
+      calls.set(calls.get() + 1);
+      Err(status_error(503, Some(86400)))
+    });
+    result.unwrap_err();
+    assert_eq!(2, calls.get());
+    assert_eq!(Duration::from_millis(MAX_DELAY_MS), SLEPT.with(|slept| slept.get()));
+  }
+
+  #[test]
+  fn backoff_is_exponential_with_jitter() {
+    let policy = RetryPolicy::new(10, 500);
+    assert_eq!(500, policy.backoff_ms(1));
+    assert_eq!(1000, policy.backoff_ms(2));
+    assert_eq!(4000, policy.backoff_ms(4));
+    assert_eq!(MAX_DELAY_MS, policy.backoff_ms(200));
+
+    for _ in 0..20 {
+      let delay = policy.delay_for(3, &status_error(503, None));
+      assert!(delay >= Duration::from_millis(1000));
+      assert!(delay <= Duration::from_millis(2000));
+    }
+  }
+}
@@ -0,0 +1,218 @@
+use hyper::Url;
+use regex::Regex;
+use result::{FdownError, Result};
+
+/// Prefix for config keys that hold extra rewrite rules, e.g.
+/// `rewrite.myhost = ^img\.example\.com$ _small\. _large.`
+pub const CONFIG_PREFIX: &'static str = "rewrite.";
+
+// (host, pattern, replacements from best to worst)
+const BUILTIN_RULES: &'static [(&'static str, &'static str, &'static [&'static str])] = &[
+  // Tumblr: tumblr_abc_500.jpg -> tumblr_abc_1280.jpg, or failing that, tumblr_abc_raw.jpg
+  (r"(^|\.)media\.tumblr\.com$", r"_(\d+)(\.[[:alnum:]]+)$", &["_1280$2", "_raw$2"]),
+  // Flickr: the _b suffix is the largest size that shares the thumbnail's secret.
+  (r"(^|\.)static\.?flickr\.com$", r"_[sqtmnzc](\.[[:alpha:]]+)$", &["_b$1"]),
+  (r"(^|\.)static\.?flickr\.com$", r"/(\d+_[[:xdigit:]]+)(\.[[:alpha:]]+)$", &["/${1}_b$2"]),
+  // Imgur: a one-letter suffix on a seven character id is a thumbnail.
+  (r"^i\.imgur\.com$", r"/([[:alnum:]]{7})[sbtmlh](\.[[:alpha:]]+)$", &["/$1$2"]),
+  // Blogger: /s400/ and friends are scaled copies, /s0/ is the original.
+  (r"(^|\.)(bp\.blogspot\.com|googleusercontent\.com)$", r"/(s|w|h)\d+(-[^/]*)?/", &["/s0/"]),
+  // WordPress: ?w=, ?resize= and ?fit= ask Photon for a scaled copy.
+  (r"(^|\.)(wp\.com|files\.wordpress\.com)$", r"\?.*$", &[""]),
+];
+
+struct RewriteRule {
+  host: Regex,
+  pattern: Regex,
+  replacements: Vec<String>,
+}
+
+/// Rewrites image URLs to ask for the largest available size, using rules matched on the
+/// URL's host.
+pub struct Rewriter {
+  rules: Vec<RewriteRule>,
+}
+
+impl Rewriter {
+  /// A rewriter with the built-in rules.
+  pub fn new() -> Rewriter {
+    let mut rewriter = Rewriter { rules: Vec::new() };
+    for &(host, pattern, replacements) in BUILTIN_RULES {
+      rewriter.rules.push(RewriteRule {
+        host: Regex::new(host).unwrap(),
+        pattern: Regex::new(pattern).unwrap(),
+        replacements: replacements.iter().map(|r| r.to_string()).collect(),
+      });
+    }
+    rewriter
+  }
+
+  /// Adds a rule from the config file, ahead of the built-in rules. `spec` is a host regex,
+  /// a URL regex and zero or more replacements, best first, separated by whitespace.
+  pub fn add_rule(&mut self, spec: &str) -> Result<()> {
+    let parts: Vec<&str> = spec.split_whitespace().collect();
+    if parts.len() < 2 {
+      return Err(FdownError::BadConfig(format!("Rewrite rule needs a host regex, a url regex \
+                                                and optional replacements: \"{}\"",
+                                               spec)));
+    }
+    let mut replacements: Vec<String> = parts[2..].iter().map(|r| r.to_string()).collect();
+    if replacements.is_empty() {
+      replacements.push(String::new());
+    }
+    let rule = RewriteRule {
+      host: try!(rule_regex(parts[0], spec)),
+      pattern: try!(rule_regex(parts[1], spec)),
+      replacements: replacements,
+    };
+    let user_rules = self.rules.len() - BUILTIN_RULES.len();
+    self.rules.insert(user_rules, rule);
+    Ok(())
+  }
+
+  /// The URLs to try for `url`, best first. Every rule whose host and pattern match is
+  /// applied, and the unmodified `url` is always last.
+  pub fn candidates(&self, url: &str) -> Vec<String> {
+    let host = match Url::parse(url).ok().and_then(|u| u.host_str().map(|h| h.to_lowercase())) {
+      Some(host) => host,
+      None => return vec![url.to_string()],
+    };
+    let mut candidates = vec![url.to_string()];
+    for rule in &self.rules {
+      if !rule.host.is_match(&host) {
+        continue;
+      }
+      let mut next = Vec::new();
+      for candidate in &candidates {
+        if rule.pattern.is_match(candidate) {
+          for replacement in &rule.replacements {
+            push_unique(&mut next, rule.pattern.replace(candidate, replacement.as_str()));
+          }
+        } else {
+          push_unique(&mut next, candidate.clone());
+        }
+      }
+      candidates = next;
+    }
+    push_unique(&mut candidates, url.to_string());
+    candidates
+  }
+}
+
+fn push_unique(urls: &mut Vec<String>, url: String) {
+  if !urls.contains(&url) {
+    urls.push(url);
+  }
+}
+
+fn rule_regex(regex: &str, spec: &str) -> Result<Regex> {
+  Regex::new(regex)
+    .map_err(|e| FdownError::BadConfig(format!("Bad regex in rewrite rule \"{}\": {}", spec, e)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn check(from: &str, to: &str) {
+    assert_eq!(to, Rewriter::new().candidates(from)[0]);
+  }
+
+  #[test]
+  fn tumblr() {
+    check("http://67.media.tumblr.com/5446/tumblr_nz1fzdpOcj1s2rw5ho1_500.jpg",
+          "http://67.media.tumblr.com/5446/tumblr_nz1fzdpOcj1s2rw5ho1_1280.jpg");
+    check("http://media.tumblr.com/tumblr_nz1fzdpOcj1s2rw5ho1_400.gif",
+          "http://media.tumblr.com/tumblr_nz1fzdpOcj1s2rw5ho1_1280.gif");
+  }
+
+  #[test]
+  fn tumblr_candidates() {
+    assert_eq!(vec!["http://media.tumblr.com/tumblr_abc_1280.jpg",
+                    "http://media.tumblr.com/tumblr_abc_raw.jpg",
+                    "http://media.tumblr.com/tumblr_abc_500.jpg"],
+               Rewriter::new().candidates("http://media.tumblr.com/tumblr_abc_500.jpg"));
+    assert_eq!(vec!["http://media.tumblr.com/tumblr_abc_1280.jpg",
+                    "http://media.tumblr.com/tumblr_abc_raw.jpg"],
+               Rewriter::new().candidates("http://media.tumblr.com/tumblr_abc_1280.jpg"));
+  }
+
+  #[test]
+  fn only_matching_hosts() {
+    check("http://example.com/photo_500.jpg", "http://example.com/photo_500.jpg");
+    check("http://media.tumblr.com.example.com/photo_500.jpg",
+          "http://media.tumblr.com.example.com/photo_500.jpg");
+    assert_eq!(vec!["http://example.com/photo_500.jpg"],
+               Rewriter::new().candidates("http://example.com/photo_500.jpg"));
+  }
+
+  #[test]
+  fn flickr() {
+    check("https://farm6.staticflickr.com/5330/9318201839_0ab3c1e5d2_m.jpg",
+          "https://farm6.staticflickr.com/5330/9318201839_0ab3c1e5d2_b.jpg");
+    check("https://farm6.staticflickr.com/5330/9318201839_0ab3c1e5d2.jpg",
+          "https://farm6.staticflickr.com/5330/9318201839_0ab3c1e5d2_b.jpg");
+    check("https://farm6.staticflickr.com/5330/9318201839_0ab3c1e5d2_b.jpg",
+          "https://farm6.staticflickr.com/5330/9318201839_0ab3c1e5d2_b.jpg");
+    assert_eq!(2,
+               Rewriter::new()
+                 .candidates("https://farm6.staticflickr.com/5330/9318201839_0ab3c1e5d2_m.jpg")
+                 .len());
+  }
+
+  #[test]
+  fn imgur() {
+    check("http://i.imgur.com/AbCdEfGm.jpg", "http://i.imgur.com/AbCdEfG.jpg");
+    check("http://i.imgur.com/AbCdEfG.jpg", "http://i.imgur.com/AbCdEfG.jpg");
+    check("http://i.imgur.com/AbCdE.png", "http://i.imgur.com/AbCdE.png");
+  }
+
+  #[test]
+  fn blogger() {
+    check("https://1.bp.blogspot.com/-abc/VtL/AAA/xyz/s400/photo.jpg",
+          "https://1.bp.blogspot.com/-abc/VtL/AAA/xyz/s0/photo.jpg");
+    check("https://1.bp.blogspot.com/-abc/VtL/AAA/xyz/s1600-h/photo.jpg",
+          "https://1.bp.blogspot.com/-abc/VtL/AAA/xyz/s0/photo.jpg");
+  }
+
+  #[test]
+  fn wordpress() {
+    check("http://i0.wp.com/media.boingboing.net/wp-content/uploads/2016/08/5ypJ9A.gif?fit=360%2C270",
+          "http://i0.wp.com/media.boingboing.net/wp-content/uploads/2016/08/5ypJ9A.gif");
+    check("https://someblog.files.wordpress.com/2016/08/photo.jpg?w=300&h=200",
+          "https://someblog.files.wordpress.com/2016/08/photo.jpg");
+  }
+
+  #[test]
+  fn user_rules_come_first() {
+    let mut rewriter = Rewriter::new();
+    rewriter.add_rule(r"media\.tumblr\.com$ _500\. _raw.").unwrap();
+    rewriter.add_rule(r"^img\.example\.com$ /thumbs/ /full/ /large/").unwrap();
+    assert_eq!("http://media.tumblr.com/tumblr_abc_raw.jpg",
+               rewriter.candidates("http://media.tumblr.com/tumblr_abc_500.jpg")[0]);
+    assert_eq!(vec!["http://img.example.com/full/a.jpg",
+                    "http://img.example.com/large/a.jpg",
+                    "http://img.example.com/thumbs/a.jpg"],
+               rewriter.candidates("http://img.example.com/thumbs/a.jpg"));
+  }
+
+  #[test]
+  fn rule_without_replacement() {
+    let mut rewriter = Rewriter::new();
+    rewriter.add_rule(r"^img\.example\.com$ \?.*$").unwrap();
+    assert_eq!("http://img.example.com/a.jpg",
+               rewriter.candidates("http://img.example.com/a.jpg?size=small")[0]);
+  }
+
+  #[test]
+  fn bad_rules() {
+    let mut rewriter = Rewriter::new();
+    rewriter.add_rule("onlyonepart").unwrap_err();
+    rewriter.add_rule("(unclosed b").unwrap_err();
+  }
+
+  #[test]
+  fn unparseable_url() {
+    assert_eq!(vec!["not a url"], Rewriter::new().candidates("not a url"));
+  }
+}
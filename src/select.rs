@@ -0,0 +1,250 @@
+use generated::{EntryDetail, SubscriptionDetail};
+use regex::{self, Regex};
+use result::{FdownError, Result};
+use std::collections::HashSet;
+
+/// Matches feed titles, ignoring case. `/.../` is a regex that may match anywhere in the
+/// title. Anything else is a glob that must match the whole title, where `*` is any run of
+/// characters and `?` is any one character.
+pub struct TitlePattern {
+  regex: Regex,
+}
+
+impl TitlePattern {
+  pub fn new(pattern: &str) -> Result<TitlePattern> {
+    let source = if pattern.len() >= 2 && pattern.starts_with('/') && pattern.ends_with('/') {
+      format!("(?i){}", &pattern[1..pattern.len() - 1])
+    } else {
+      format!("(?i)^{}$", glob_to_regex(pattern))
+    };
+    let regex = try!(Regex::new(&source)
+      .map_err(|e| FdownError::BadConfig(format!("Bad feed pattern \"{}\": {}", pattern, e))));
+    Ok(TitlePattern { regex: regex })
+  }
+
+  pub fn matches(&self, title: &str) -> bool {
+    self.regex.is_match(title)
+  }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+  let mut source = String::new();
+  for ch in glob.chars() {
+    match ch {
+      '*' => source.push_str(".*"),
+      '?' => source.push('.'),
+      _ => source.push_str(&regex::quote(&ch.to_string())),
+    }
+  }
+  source
+}
+
+/// Which feeds to take entries from. With no inclusions, every feed is included. Exclusions
+/// win over inclusions.
+pub struct FeedSelector {
+  categories: Vec<String>,
+  feeds: Vec<TitlePattern>,
+  feed_ids: Vec<String>,
+  exclude_categories: Vec<String>,
+  exclude_feeds: Vec<TitlePattern>,
+}
+
+impl FeedSelector {
+  pub fn new() -> FeedSelector {
+    FeedSelector {
+      categories: Vec::new(),
+      feeds: Vec::new(),
+      feed_ids: Vec::new(),
+      exclude_categories: Vec::new(),
+      exclude_feeds: Vec::new(),
+    }
+  }
+
+  /// Includes the feeds in the category with this label.
+  pub fn include_category(&mut self, label: &str) {
+    self.categories.push(label.to_string());
+  }
+
+  /// Includes the feeds whose titles match `pattern`.
+  pub fn include_feed(&mut self, pattern: &str) -> Result<()> {
+    self.feeds.push(try!(TitlePattern::new(pattern)));
+    Ok(())
+  }
+
+  /// Includes the feed with this stream id, e.g. `feed/http://example.com/rss`, whether or not
+  /// it is subscribed to.
+  pub fn include_feed_id(&mut self, id: &str) {
+    self.feed_ids.push(id.to_string());
+  }
+
+  pub fn exclude_category(&mut self, label: &str) {
+    self.exclude_categories.push(label.to_string());
+  }
+
+  pub fn exclude_feed(&mut self, pattern: &str) -> Result<()> {
+    self.exclude_feeds.push(try!(TitlePattern::new(pattern)));
+    Ok(())
+  }
+
+  /// Whether anything has been included, rather than every feed.
+  pub fn has_inclusions(&self) -> bool {
+    !(self.categories.is_empty() && self.feeds.is_empty() && self.feed_ids.is_empty())
+  }
+
+  /// Whether `filter` needs the subscriptions to resolve titles and categories.
+  pub fn needs_subscriptions(&self) -> bool {
+    !(self.categories.is_empty() && self.feeds.is_empty() &&
+      self.exclude_categories.is_empty() && self.exclude_feeds.is_empty())
+  }
+
+  /// Resolves the selection against `subs`.
+  pub fn filter(&self, subs: &[SubscriptionDetail]) -> StreamFilter {
+    let include = if self.has_inclusions() {
+      let mut ids: HashSet<String> = self.feed_ids.iter().cloned().collect();
+      ids.extend(subs.iter()
+        .filter(|sub| in_category(sub, &self.categories) || title_matches(sub, &self.feeds))
+        .map(|sub| sub.id.clone()));
+      Some(ids)
+    } else {
+      None
+    };
+    let exclude = subs.iter()
+      .filter(|sub| {
+        in_category(sub, &self.exclude_categories) || title_matches(sub, &self.exclude_feeds)
+      })
+      .map(|sub| sub.id.clone())
+      .collect();
+    StreamFilter {
+      include: include,
+      exclude: exclude,
+    }
+  }
+}
+
+fn in_category(sub: &SubscriptionDetail, labels: &[String]) -> bool {
+  sub.categories
+    .iter()
+    .any(|cat| cat.label.as_ref().map_or(false, |label| labels.contains(label)))
+}
+
+fn title_matches(sub: &SubscriptionDetail, patterns: &[TitlePattern]) -> bool {
+  sub.title.as_ref().map_or(false, |title| patterns.iter().any(|p| p.matches(title)))
+}
+
+/// The selected feeds, as the stream ids that entries come from.
+pub struct StreamFilter {
+  // None when every feed not excluded is selected.
+  include: Option<HashSet<String>>,
+  exclude: HashSet<String>,
+}
+
+impl StreamFilter {
+  pub fn matches(&self, entry: &EntryDetail) -> bool {
+    match entry.origin {
+      Some(ref origin) => {
+        self.include.as_ref().map_or(true, |ids| ids.contains(&origin.stream_id)) &&
+        !self.exclude.contains(&origin.stream_id)
+      }
+      None => self.include.is_none(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use generated::{EntryDetail, EntryDetailOrigin, SubscriptionDetail,
+                  SubscriptionDetailCategory};
+  use super::*;
+
+  fn sub(id: &str, title: &str, category: &str) -> SubscriptionDetail {
+    SubscriptionDetail {
+      id: id.to_string(),
+      title: Some(title.to_string()),
+      categories: vec![SubscriptionDetailCategory {
+                         id: format!("user/u/category/{}", category),
+                         label: Some(category.to_string()),
+                       }],
+      ..Default::default()
+    }
+  }
+
+  fn subs() -> Vec<SubscriptionDetail> {
+    vec![sub("feed/a", "Cats Daily", "pets"),
+         sub("feed/b", "Dogs Weekly", "pets"),
+         sub("feed/c", "Rust Blog", "tech")]
+  }
+
+  fn entry(stream_id: &str) -> EntryDetail {
+    EntryDetail {
+      id: "entry".to_string(),
+      origin: Some(EntryDetailOrigin { stream_id: stream_id.to_string(), ..Default::default() }),
+      ..Default::default()
+    }
+  }
+
+  fn selected(selector: &FeedSelector) -> Vec<&'static str> {
+    let filter = selector.filter(&subs());
+    vec!["feed/a", "feed/b", "feed/c", "feed/unsubscribed"]
+      .into_iter()
+      .filter(|id| filter.matches(&entry(id)))
+      .collect()
+  }
+
+  #[test]
+  fn glob() {
+    let pattern = TitlePattern::new("cats*").unwrap();
+    assert!(pattern.matches("Cats Daily"));
+    assert!(!pattern.matches("Big Cats"));
+    assert!(TitlePattern::new("?ogs weekly").unwrap().matches("Dogs Weekly"));
+    assert!(TitlePattern::new("a.b (c)").unwrap().matches("A.B (C)"));
+    assert!(!TitlePattern::new("a.b").unwrap().matches("axb"));
+  }
+
+  #[test]
+  fn regex() {
+    let pattern = TitlePattern::new("/da(il)?y/").unwrap();
+    assert!(pattern.matches("Cats Daily"));
+    assert!(!pattern.matches("Dogs Weekly"));
+    assert!(TitlePattern::new("/[/").is_err());
+  }
+
+  #[test]
+  fn everything() {
+    let selector = FeedSelector::new();
+    assert!(!selector.has_inclusions());
+    assert!(!selector.needs_subscriptions());
+    assert_eq!(vec!["feed/a", "feed/b", "feed/c", "feed/unsubscribed"], selected(&selector));
+    assert!(selector.filter(&[]).matches(&Default::default()));
+  }
+
+  #[test]
+  fn includes() {
+    let mut selector = FeedSelector::new();
+    selector.include_category("tech");
+    selector.include_feed("cats*").unwrap();
+    selector.include_feed_id("feed/unsubscribed");
+    assert!(selector.needs_subscriptions());
+    assert_eq!(vec!["feed/a", "feed/c", "feed/unsubscribed"], selected(&selector));
+    assert!(!selector.filter(&subs()).matches(&Default::default()));
+  }
+
+  #[test]
+  fn feed_ids_only() {
+    let mut selector = FeedSelector::new();
+    selector.include_feed_id("feed/b");
+    assert!(selector.has_inclusions());
+    assert!(!selector.needs_subscriptions());
+    assert_eq!(vec!["feed/b"], selected(&selector));
+  }
+
+  #[test]
+  fn excludes() {
+    let mut selector = FeedSelector::new();
+    selector.exclude_feed("/weekly/").unwrap();
+    assert_eq!(vec!["feed/a", "feed/c", "feed/unsubscribed"], selected(&selector));
+
+    selector.include_category("pets");
+    selector.exclude_category("tech");
+    assert_eq!(vec!["feed/a"], selected(&selector));
+  }
+}
@@ -1,43 +1,166 @@
 #[derive(Debug,Deserialize)]
 pub struct StreamsIdsResponse {
-  continuation: Option<String>,
+  pub continuation: Option<String>,
   pub ids: Vec<String>,
 }
 
-#[derive(Debug,Deserialize,PartialEq)]
+#[derive(Debug,Default,Deserialize,PartialEq,Serialize)]
 pub struct EntryDetailVisual {
+  /// The image's URL, or "none" when Feedly found no image.
+  #[serde(skip_serializing_if="Option::is_none")]
   pub url: Option<String>,
+  #[serde(skip_serializing_if="Option::is_none")]
   #[serde(rename="contentType")]
   pub content_type: Option<String>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub width: Option<u32>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub height: Option<u32>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub processor: Option<String>,
 }
 
-#[derive(Debug,Deserialize,PartialEq)]
+#[derive(Debug,Default,Deserialize,PartialEq,Serialize)]
 pub struct EntryDetailOrigin {
   #[serde(rename="streamId")]
   pub stream_id: String,
+  #[serde(skip_serializing_if="Option::is_none")]
   pub title: Option<String>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  #[serde(rename="htmlUrl")]
+  pub html_url: Option<String>,
 }
 
-#[derive(Debug,Deserialize,PartialEq)]
+#[derive(Debug,Deserialize,PartialEq,Serialize)]
+pub struct EntryDetailContent {
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub content: Option<String>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub direction: Option<String>,
+}
+
+#[derive(Debug,Deserialize,PartialEq,Serialize)]
+pub struct EntryDetailLink {
+  pub href: String,
+  #[serde(skip_serializing_if="Option::is_none")]
+  #[serde(rename="type")]
+  pub type_field: Option<String>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub length: Option<u64>,
+}
+
+/// A category or tag that the user has put an entry in.
+#[derive(Debug,Deserialize,PartialEq,Serialize)]
+pub struct EntryDetailLabel {
+  pub id: String,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub label: Option<String>,
+}
+
+#[derive(Debug,Deserialize,PartialEq,Serialize)]
+pub struct EntryDetailWebfeeds {
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub logo: Option<String>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub icon: Option<String>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub wordmark: Option<String>,
+}
+
+#[derive(Debug,Default,Deserialize,PartialEq,Serialize)]
 pub struct EntryDetail {
   pub id: String,
+  #[serde(skip_serializing_if="Option::is_none")]
+  #[serde(rename="originId")]
+  pub origin_id: Option<String>,
+  #[serde(skip_serializing_if="Option::is_none")]
   pub fingerprint: Option<String>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub title: Option<String>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub author: Option<String>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub published: Option<Timestamp>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub updated: Option<Timestamp>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub crawled: Option<Timestamp>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub recrawled: Option<Timestamp>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub unread: Option<bool>,
+  #[serde(skip_serializing_if="Option::is_none")]
   pub visual: Option<EntryDetailVisual>,
+  #[serde(skip_serializing_if="Option::is_none")]
   pub origin: Option<EntryDetailOrigin>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub summary: Option<EntryDetailContent>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub content: Option<EntryDetailContent>,
+  /// Links to the entry on the web, usually a single text/html page.
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub alternate: Option<Vec<EntryDetailLink>>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub canonical: Option<Vec<EntryDetailLink>>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub enclosure: Option<Vec<EntryDetailLink>>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub keywords: Option<Vec<String>>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub tags: Option<Vec<EntryDetailLabel>>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub categories: Option<Vec<EntryDetailLabel>>,
+  /// How popular the entry is: roughly its shares and saves.
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub engagement: Option<u64>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  #[serde(rename="engagementRate")]
+  pub engagement_rate: Option<f64>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub webfeeds: Option<EntryDetailWebfeeds>,
 }
 
-#[derive(Debug,Deserialize)]
+#[derive(Debug,Default,Deserialize,Serialize)]
 pub struct SubscriptionDetailCategory {
   pub id: String,
+  #[serde(skip_serializing_if="Option::is_none")]
   pub label: Option<String>,
 }
 
-#[derive(Debug,Deserialize)]
+#[derive(Debug,Default,Deserialize,Serialize)]
 pub struct SubscriptionDetail {
   pub id: String,
+  #[serde(skip_serializing_if="Option::is_none")]
   pub website: Option<String>,
+  #[serde(skip_serializing_if="Option::is_none")]
   pub title: Option<String>,
   pub categories: Vec<SubscriptionDetailCategory>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub updated: Option<Timestamp>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub subscribers: Option<u64>,
+  /// Average entries per week.
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub velocity: Option<f64>,
+  /// E.g. "dormant" or "dead" for feeds that have stopped updating.
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub state: Option<String>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  #[serde(rename="contentType")]
+  pub content_type: Option<String>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub partial: Option<bool>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub topics: Option<Vec<String>>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  #[serde(rename="iconUrl")]
+  pub icon_url: Option<String>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  #[serde(rename="visualUrl")]
+  pub visual_url: Option<String>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  #[serde(rename="coverUrl")]
+  pub cover_url: Option<String>,
 }
 
 #[derive(Debug,Serialize)]
@@ -49,6 +172,50 @@ pub struct MarkerRequestBody {
   pub entry_ids: Vec<String>,
 }
 
+#[derive(Debug,Serialize)]
+pub struct TagRequestBody {
+  #[serde(rename="entryIds")]
+  pub entry_ids: Vec<String>,
+}
+
+#[derive(Debug,Serialize)]
+pub struct DropboxDeleteArg<'a> {
+  pub path: &'a str,
+}
+
+#[derive(Debug,Serialize)]
+pub struct DropboxListFolderArg<'a> {
+  pub path: &'a str,
+  pub recursive: bool,
+}
+
+#[derive(Debug,Serialize)]
+pub struct DropboxListFolderContinueArg<'a> {
+  pub cursor: &'a str,
+}
+
+#[derive(Debug,Deserialize)]
+pub struct DropboxMetadata {
+  #[serde(rename=".tag")]
+  pub tag: String,
+  pub path_display: Option<String>,
+  pub content_hash: Option<String>,
+}
+
+/// What Dropbox returns for an uploaded file.
+#[derive(Debug,Deserialize)]
+pub struct DropboxFileMetadata {
+  /// Where the file ended up, which differs from the requested path if it was renamed.
+  pub path_display: String,
+}
+
+#[derive(Debug,Deserialize)]
+pub struct DropboxListFolderResult {
+  pub entries: Vec<DropboxMetadata>,
+  pub cursor: String,
+  pub has_more: bool,
+}
+
 #[derive(Debug,Serialize)]
 pub struct DropboxUploadAPI<'a> {
   pub path: &'a str,
@@ -56,3 +223,87 @@ pub struct DropboxUploadAPI<'a> {
   pub autorename: bool,
   pub mute: bool,
 }
+
+#[derive(Debug,Serialize)]
+pub struct DropboxUploadSessionStartArg {
+  pub close: bool,
+}
+
+#[derive(Debug,Deserialize)]
+pub struct DropboxUploadSessionStartResult {
+  pub session_id: String,
+}
+
+#[derive(Debug,Serialize)]
+pub struct DropboxUploadSessionCursor<'a> {
+  pub session_id: &'a str,
+  pub offset: u64,
+}
+
+#[derive(Debug,Serialize)]
+pub struct DropboxUploadSessionAppendArg<'a> {
+  pub cursor: DropboxUploadSessionCursor<'a>,
+  pub close: bool,
+}
+
+#[derive(Debug,Serialize)]
+pub struct DropboxUploadSessionFinishArg<'a> {
+  pub cursor: DropboxUploadSessionCursor<'a>,
+  pub commit: DropboxUploadAPI<'a>,
+}
+
+#[derive(Debug,Deserialize,PartialEq,Serialize)]
+pub struct LedgerRecord {
+  #[serde(rename="entryId")]
+  pub entry_id: String,
+  pub fingerprint: Option<String>,
+  pub url: String,
+  pub path: String,
+  /// Seconds since the epoch when the image was stored.
+  pub stored: u64,
+  pub sha256: Option<String>,
+  /// True if the image was not stored because `path` already had the same contents, or a
+  /// larger copy of the same picture.
+  pub duplicate: Option<bool>,
+  /// Perceptual hash, for spotting resized copies.
+  pub dhash: Option<u64>,
+  /// Width times height.
+  pub pixels: Option<u64>,
+  /// The path of a smaller copy of the same picture that this image replaced, and that was
+  /// removed from the sink.
+  pub replaces: Option<String>,
+}
+
+/// The error body returned by Feedly (`errorMessage`) or Dropbox (`error_summary`).
+#[derive(Debug,Deserialize)]
+pub struct ApiErrorBody {
+  #[serde(rename="errorMessage")]
+  pub error_message: Option<String>,
+  pub error_summary: Option<String>,
+  /// OAuth token endpoints use `error` and `error_description`.
+  pub error_description: Option<String>,
+}
+
+/// The body of a request to Feedly's token endpoint, for either grant type.
+#[derive(Debug,Serialize)]
+pub struct FeedlyTokenRequest<'a> {
+  pub grant_type: &'a str,
+  pub client_id: &'a str,
+  pub client_secret: &'a str,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub refresh_token: Option<&'a str>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub code: Option<&'a str>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub redirect_uri: Option<&'a str>,
+}
+
+#[derive(Debug,Deserialize)]
+pub struct OAuthTokenResponse {
+  /// Feedly's user id. Dropbox leaves it out.
+  pub id: Option<String>,
+  pub access_token: String,
+  pub refresh_token: Option<String>,
+  /// Seconds until `access_token` expires.
+  pub expires_in: Option<u64>,
+}
@@ -0,0 +1,237 @@
+use download::Download;
+use dropbox::Dropbox;
+use hash::ContentHashes;
+use result::{FdownError, Result};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+pub const DEFAULT_DROPBOX_FOLDER: &'static str = "/Media/Porn/Inbox";
+
+/// A place to put downloaded images. Sinks are shared by the download workers.
+pub trait Sink: Sync {
+  /// Store `contents` at `path`, which is relative to the sink's root and may contain
+  /// directories. Returns the path actually written, which differs from `path` if that
+  /// name was already taken.
+  fn store(&self, path: &str, contents: &Download) -> Result<String>;
+
+  /// Removes the file at `path`, which was stored earlier.
+  fn remove(&self, path: &str) -> Result<()>;
+
+  /// The path of a file already in the sink with the same contents, if the sink can tell.
+  fn find_duplicate(&self, _hashes: &ContentHashes) -> Result<Option<String>> {
+    Ok(None)
+  }
+}
+
+/// Uploads images to a folder in Dropbox.
+pub struct DropboxSink {
+  dropbox: Dropbox,
+  folder: String,
+  // content_hash -> path, relative to the folder, for the files already in it. Loaded on
+  // first use.
+  remote_hashes: Mutex<Option<HashMap<String, String>>>,
+}
+
+impl DropboxSink {
+  pub fn new(dropbox: Dropbox, folder: &str) -> DropboxSink {
+    DropboxSink {
+      dropbox: dropbox,
+      folder: folder.trim_right_matches('/').to_string(),
+      remote_hashes: Mutex::new(None),
+    }
+  }
+}
+
+impl Sink for DropboxSink {
+  fn store(&self, path: &str, contents: &Download) -> Result<String> {
+    let stored = try!(self.dropbox.upload(&format!("{}/{}", self.folder, path), contents));
+    Ok(relative_path(&self.folder, &stored))
+  }
+
+  fn remove(&self, path: &str) -> Result<()> {
+    self.dropbox.delete(format!("{}/{}", self.folder, path).as_str())
+  }
+
+  fn find_duplicate(&self, hashes: &ContentHashes) -> Result<Option<String>> {
+    // Other workers wait here while the folder is listed, rather than each listing it.
+    let mut remote = self.remote_hashes.lock().unwrap();
+    if remote.is_none() {
+      let hashes = try!(self.dropbox.content_hashes(&self.folder));
+      *remote = Some(relative_paths(&self.folder, hashes));
+    }
+    Ok(remote.as_ref().and_then(|r| r.get(&hashes.dropbox)).cloned())
+  }
+}
+
+/// `path` relative to `folder`. Dropbox paths ignore case, so the prefix may be cased
+/// differently from the configured folder.
+fn relative_path(folder: &str, path: &str) -> String {
+  let prefix = format!("{}/", folder);
+  let len = prefix.len();
+  if path.len() > len && path.is_char_boundary(len) &&
+     path[..len].to_lowercase() == prefix.to_lowercase() {
+    return path[len..].to_string();
+  }
+  path.to_string()
+}
+
+/// Makes the paths in `hashes`, which Dropbox lists in full, relative to `folder` like the
+/// paths the rest of fdown records.
+fn relative_paths(folder: &str, hashes: HashMap<String, String>) -> HashMap<String, String> {
+  hashes.into_iter().map(|(hash, path)| (hash, relative_path(folder, &path))).collect()
+}
+
+/// Writes images to a directory on the local disk.
+pub struct DirectorySink {
+  root: PathBuf,
+}
+
+impl DirectorySink {
+  pub fn new(root: &str) -> DirectorySink {
+    DirectorySink { root: PathBuf::from(root) }
+  }
+}
+
+impl Sink for DirectorySink {
+  fn store(&self, path: &str, contents: &Download) -> Result<String> {
+    let path = PathBuf::from(path);
+    if let Some(parent) = self.root.join(&path).parent() {
+      try!(fs::create_dir_all(parent));
+    }
+    // Claim the name with create_new so that concurrent workers never overwrite each other.
+    let mut candidate = path.clone();
+    let mut num = 0;
+    loop {
+      match OpenOptions::new().write(true).create_new(true).open(self.root.join(&candidate)) {
+        Ok(mut file) => {
+          let copied = contents.reader().and_then(|mut reader| {
+            io::copy(&mut reader, &mut file).map_err(FdownError::from)
+          });
+          if let Err(e) = copied {
+            // Don't leave a truncated image holding the name.
+            let _ = fs::remove_file(self.root.join(&candidate));
+            return Err(e);
+          }
+          return Ok(candidate.to_string_lossy().into_owned());
+        }
+        Err(ref e) if e.kind() == ErrorKind::AlreadyExists => {
+          num += 1;
+          candidate = try!(numbered_path(&path, num));
+        }
+        Err(e) => return Err(FdownError::from(e)),
+      }
+    }
+  }
+
+  fn remove(&self, path: &str) -> Result<()> {
+    Ok(try!(fs::remove_file(self.root.join(path))))
+  }
+}
+
+fn add_number_suffix(stem: &OsStr, num: usize) -> Result<String> {
+  if let Some(stem) = stem.to_str() {
+    return Ok(format!("{}_({})", stem, num));
+  }
+  Err(FdownError::BadFormat(format!("cannot append number to stem: {}",
+                                    stem.to_string_lossy())))
+}
+
+fn numbered_path(path: &Path, num: usize) -> Result<PathBuf> {
+  let extension = path.extension().unwrap_or(OsStr::new(""));
+  if let Some(stem) = path.file_stem() {
+    let new_name = try!(add_number_suffix(stem, num));
+    return Ok(path.with_file_name(new_name).with_extension(extension));
+  }
+  Err(FdownError::BadFormat(format!("unable to find file stem in path: {}",
+                                    path.to_string_lossy())))
+}
+
+#[cfg(test)]
+mod tests {
+  use download::Download;
+  use std::collections::HashMap;
+  use std::env;
+  use std::fs::{self, File};
+  use std::io::Read;
+  use std::path::PathBuf;
+  use super::*;
+
+  fn scratch_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("fdown_sink_{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+  }
+
+  fn download(s: &str) -> Download {
+    Download::in_memory(s.as_bytes().to_vec(), None)
+  }
+
+  fn contents_of(path: PathBuf) -> String {
+    let mut s = String::new();
+    File::open(path).unwrap().read_to_string(&mut s).unwrap();
+    s
+  }
+
+  #[test]
+  fn directory_sink_writes_file() {
+    let dir = scratch_dir("writes");
+    let sink = DirectorySink::new(dir.to_str().unwrap());
+    assert_eq!("foo.jpg", sink.store("foo.jpg", &download("hello")).unwrap());
+    assert_eq!("hello", contents_of(dir.join("foo.jpg")));
+  }
+
+  #[test]
+  fn directory_sink_does_not_overwrite() {
+    let dir = scratch_dir("overwrite");
+    let sink = DirectorySink::new(dir.to_str().unwrap());
+    assert_eq!("foo.jpg", sink.store("foo.jpg", &download("first")).unwrap());
+    assert_eq!("foo_(1).jpg", sink.store("foo.jpg", &download("second")).unwrap());
+    assert_eq!("foo_(2).jpg", sink.store("foo.jpg", &download("third")).unwrap());
+    assert_eq!("first", contents_of(dir.join("foo.jpg")));
+    assert_eq!("second", contents_of(dir.join("foo_(1).jpg")));
+    assert_eq!("third", contents_of(dir.join("foo_(2).jpg")));
+  }
+
+  #[test]
+  fn directory_sink_creates_directories() {
+    let dir = scratch_dir("subdirs");
+    let sink = DirectorySink::new(dir.to_str().unwrap());
+    sink.store("Pics/2016/foo.jpg", &download("hello")).unwrap();
+    assert_eq!("hello", contents_of(dir.join("Pics").join("2016").join("foo.jpg")));
+    assert_eq!("Pics/2016/foo_(1).jpg",
+               sink.store("Pics/2016/foo.jpg", &download("again")).unwrap());
+  }
+
+  #[test]
+  fn directory_sink_removes() {
+    let dir = scratch_dir("removes");
+    let sink = DirectorySink::new(dir.to_str().unwrap());
+    sink.store("foo.jpg", &download("hello")).unwrap();
+    sink.remove("foo.jpg").unwrap();
+    assert!(!dir.join("foo.jpg").exists());
+    sink.remove("foo.jpg").unwrap_err();
+  }
+
+  #[test]
+  fn relative_to_folder() {
+    assert_eq!("a (1).jpg", relative_path("/Inbox", "/Inbox/a (1).jpg"));
+    assert_eq!("sub/a (1).jpg", relative_path("/Media/Inbox", "/media/inbox/sub/a (1).jpg"));
+    assert_eq!("a.jpg", relative_path("", "/a.jpg"));
+    assert_eq!("/Other/a.jpg", relative_path("/Inbox", "/Other/a.jpg"));
+    assert_eq!("/Inbox", relative_path("/Inbox", "/Inbox"));
+  }
+
+  #[test]
+  fn duplicates_relative_to_folder() {
+    let mut hashes = HashMap::new();
+    hashes.insert("aaa".to_string(), "/Inbox/a.jpg".to_string());
+    hashes.insert("bbb".to_string(), "/Inbox/sub/b.jpg".to_string());
+    let relative = relative_paths("/Inbox", hashes);
+    assert_eq!("a.jpg", relative["aaa"]);
+    assert_eq!("sub/b.jpg", relative["bbb"]);
+  }
+}
@@ -0,0 +1,196 @@
+use chrono::Datelike;
+use result::{FdownError, Result};
+use timestamp::Timestamp;
+
+pub const DEFAULT_TEMPLATE: &'static str = "{filename}";
+
+const UNKNOWN: &'static str = "unknown";
+
+#[derive(Debug, PartialEq)]
+enum Field {
+  Category,
+  FeedTitle,
+  Year,
+  Month,
+  Day,
+  Filename,
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+  Literal(String),
+  Field(Field),
+}
+
+/// The values that can be substituted into a `PathTemplate`.
+#[derive(Debug, Default)]
+pub struct TemplateValues<'a> {
+  pub category: Option<&'a str>,
+  pub feed_title: Option<&'a str>,
+  pub timestamp: Option<Timestamp>,
+  pub filename: &'a str,
+}
+
+/// A destination path such as `{category}/{feed_title}/{yyyy}/{mm}/{filename}`.
+#[derive(Debug)]
+pub struct PathTemplate {
+  segments: Vec<Segment>,
+}
+
+impl PathTemplate {
+  pub fn new(template: &str) -> Result<PathTemplate> {
+    let mut segments = Vec::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+      if open > 0 {
+        segments.push(Segment::Literal(rest[..open].to_string()));
+      }
+      let close = try!(rest[open..]
+        .find('}')
+        .ok_or(FdownError::BadConfig(format!("Unclosed '{{' in path template: {}", template))));
+      let name = &rest[open + 1..open + close];
+      segments.push(Segment::Field(try!(field_for_name(name, template))));
+      rest = &rest[open + close + 1..];
+    }
+    if !rest.is_empty() {
+      segments.push(Segment::Literal(rest.to_string()));
+    }
+    if !segments.contains(&Segment::Field(Field::Filename)) {
+      return Err(FdownError::BadConfig(format!("Path template must contain {{filename}}: {}",
+                                               template)));
+    }
+    Ok(PathTemplate { segments: segments })
+  }
+
+  pub fn uses_category(&self) -> bool {
+    self.segments.contains(&Segment::Field(Field::Category))
+  }
+
+  pub fn expand(&self, values: &TemplateValues) -> String {
+    let date = values.timestamp.map(|t| t.to_naive());
+    let mut path = String::new();
+    for segment in &self.segments {
+      match *segment {
+        Segment::Literal(ref s) => path.push_str(s),
+        Segment::Field(ref field) => {
+          let value = match *field {
+            Field::Category => sanitize(values.category.unwrap_or(UNKNOWN)),
+            Field::FeedTitle => sanitize(values.feed_title.unwrap_or(UNKNOWN)),
+            Field::Year => date.map_or(UNKNOWN.to_string(), |d| format!("{:04}", d.year())),
+            Field::Month => date.map_or(UNKNOWN.to_string(), |d| format!("{:02}", d.month())),
+            Field::Day => date.map_or(UNKNOWN.to_string(), |d| format!("{:02}", d.day())),
+            Field::Filename => sanitize(values.filename),
+          };
+          path.push_str(value.as_str());
+        }
+      }
+    }
+    path
+  }
+}
+
+fn field_for_name(name: &str, template: &str) -> Result<Field> {
+  match name {
+    "category" => Ok(Field::Category),
+    "feed_title" => Ok(Field::FeedTitle),
+    "yyyy" => Ok(Field::Year),
+    "mm" => Ok(Field::Month),
+    "dd" => Ok(Field::Day),
+    "filename" => Ok(Field::Filename),
+    _ => Err(FdownError::BadConfig(format!("Unknown field, {{{}}}, in path template: {}",
+                                           name,
+                                           template))),
+  }
+}
+
+// Values come from feed titles and the like, so keep them from adding path components.
+fn sanitize(value: &str) -> String {
+  let cleaned: String = value.trim()
+    .chars()
+    .map(|c| match c {
+      '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+      c if c.is_control() => '_',
+      c => c,
+    })
+    .collect();
+  if cleaned.is_empty() || cleaned == "." || cleaned == ".." {
+    return UNKNOWN.to_string();
+  }
+  cleaned
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // 2016-08-24T12:24:38Z
+  const PUBLISHED: Timestamp = Timestamp(1472041478000);
+
+  fn values<'a>(filename: &'a str) -> TemplateValues<'a> {
+    TemplateValues {
+      category: Some("Pics"),
+      feed_title: Some("Let's Watch Girls"),
+      timestamp: Some(PUBLISHED),
+      filename: filename,
+    }
+  }
+
+  #[test]
+  fn default_template() {
+    let template = PathTemplate::new(DEFAULT_TEMPLATE).unwrap();
+    assert_eq!("foo.jpg", template.expand(&values("foo.jpg")));
+    assert!(!template.uses_category());
+  }
+
+  #[test]
+  fn all_fields() {
+    let template = PathTemplate::new("{category}/{feed_title}/{yyyy}/{mm}/{dd}/{filename}")
+      .unwrap();
+    assert_eq!("Pics/Let's Watch Girls/2016/08/24/foo.jpg",
+               template.expand(&values("foo.jpg")));
+    assert!(template.uses_category());
+  }
+
+  #[test]
+  fn literals() {
+    let template = PathTemplate::new("archive-{yyyy}/x_{filename}").unwrap();
+    assert_eq!("archive-2016/x_foo.jpg", template.expand(&values("foo.jpg")));
+  }
+
+  #[test]
+  fn missing_values() {
+    let template = PathTemplate::new("{category}/{feed_title}/{yyyy}/{filename}").unwrap();
+    let values = TemplateValues { filename: "foo.jpg", ..Default::default() };
+    assert_eq!("unknown/unknown/unknown/foo.jpg", template.expand(&values));
+  }
+
+  #[test]
+  fn sanitizes_values() {
+    let template = PathTemplate::new("{feed_title}/{filename}").unwrap();
+    let values = TemplateValues {
+      feed_title: Some("AC/DC: fans"),
+      filename: "foo.jpg",
+      ..Default::default()
+    };
+    assert_eq!("AC_DC_ fans/foo.jpg", template.expand(&values));
+
+    let values = TemplateValues { feed_title: Some(".."), filename: "foo.jpg", ..Default::default() };
+    assert_eq!("unknown/foo.jpg", template.expand(&values));
+  }
+
+  #[test]
+  fn unknown_field() {
+    PathTemplate::new("{quux}/{filename}").unwrap_err();
+  }
+
+  #[test]
+  fn unclosed_brace() {
+    PathTemplate::new("{category/{filename}").unwrap_err();
+    PathTemplate::new("{filename}/{yyyy").unwrap_err();
+  }
+
+  #[test]
+  fn requires_filename() {
+    PathTemplate::new("{category}/{yyyy}").unwrap_err();
+  }
+}
@@ -0,0 +1,59 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// A time as Feedly reports it: milliseconds since the Unix epoch, in UTC.
+#[derive(Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
+pub struct Timestamp(pub u64);
+
+impl Timestamp {
+  pub fn to_naive(&self) -> NaiveDateTime {
+    NaiveDateTime::from_timestamp((self.0 / 1000) as i64, (self.0 % 1000) as u32 * 1_000_000)
+  }
+}
+
+impl fmt::Display for Timestamp {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.to_naive())
+  }
+}
+
+// Kept as the number Feedly sends, so that entries survive a round trip unchanged.
+impl Serialize for Timestamp {
+  fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+    where S: Serializer {
+    self.0.serialize(serializer)
+  }
+}
+
+impl Deserialize for Timestamp {
+  fn deserialize<D>(deserializer: &mut D) -> Result<Timestamp, D::Error>
+    where D: Deserializer {
+    u64::deserialize(deserializer).map(Timestamp)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::{Datelike, Timelike};
+  use serde_json;
+  use super::*;
+
+  #[test]
+  fn to_naive() {
+    let t = Timestamp(1472041478123);
+    let naive = t.to_naive();
+    assert_eq!((2016, 8, 24), (naive.year(), naive.month(), naive.day()));
+    assert_eq!((12, 24, 38), (naive.hour(), naive.minute(), naive.second()));
+    assert_eq!(123_000_000, naive.nanosecond());
+    assert_eq!("2016-08-24 12:24:38.123", t.to_string());
+  }
+
+  #[test]
+  fn json() {
+    let t: Timestamp = serde_json::from_str("1472041478000").unwrap();
+    assert_eq!(Timestamp(1472041478000), t);
+    assert_eq!("1472041478000", serde_json::to_string(&t).unwrap());
+    assert!(serde_json::from_str::<Timestamp>("\"yesterday\"").is_err());
+  }
+}